@@ -0,0 +1,171 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+#[doc = "Samples the spectrum is computed on"]
+const WINDOW: usize = 2048;
+
+#[doc = "Samples buffered by a tap before being handed to the analyzer"]
+const CHECK_INTERVAL: usize = 256;
+
+#[doc = "Level shown as an empty bar in dB"]
+const FLOOR: f32 = -72.;
+
+#[doc = "Frequency range of the spectrum in Hz"]
+const FREQUENCY_RANGE: (f32, f32) = (20., 20000.);
+
+#[derive(Debug, Default)]
+struct AnalyzerState {
+    #[doc = "Last `WINDOW` samples mixed down to mono"]
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    #[doc = "Tap allowed to write, the one created last"]
+    generation: u64,
+}
+
+#[doc = "Handle on the last samples played, shared with the taps it created"]
+#[derive(Clone)]
+pub struct Analyzer {
+    state: Arc<Mutex<AnalyzerState>>,
+    fft: Arc<dyn Fft<f32>>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            fft: FftPlanner::new().plan_fft_forward(WINDOW),
+        }
+    }
+}
+
+impl Analyzer {
+    #[doc = "Wrap the mixer of the sinks, the taps created before stop feeding the analyzer so the output stream left by a device switch does not mix in"]
+    pub fn tap<I: Source>(&self, input: I) -> Tap<I> {
+        let generation = self
+            .state
+            .lock()
+            .map(|mut state| {
+                state.generation += 1;
+                state.generation
+            })
+            .unwrap_or_default();
+        Tap {
+            input,
+            state: self.state.clone(),
+            generation,
+            buffer: Vec::with_capacity(CHECK_INTERVAL),
+            frame: 0.,
+            channel: 0,
+        }
+    }
+    #[doc = "Level of `bands` log spaced frequency bands from 0 to 1"]
+    pub fn spectrum(&self, bands: usize) -> Vec<f32> {
+        let (mut buffer, sample_rate) = {
+            let Ok(state) = self.state.lock() else {
+                return vec![0.; bands];
+            };
+            if state.samples.len() < WINDOW || state.sample_rate == 0 {
+                return vec![0.; bands];
+            }
+            let buffer: Vec<Complex<f32>> = state
+                .samples
+                .iter()
+                .enumerate()
+                .map(|(index, sample)| {
+                    let hann = 0.5 - 0.5 * (2. * PI * index as f32 / (WINDOW - 1) as f32).cos();
+                    Complex::new(sample * hann, 0.)
+                })
+                .collect();
+            (buffer, state.sample_rate as f32)
+        };
+        self.fft.process(&mut buffer);
+        // A full scale sine reaches 1 once the gain of the Hann window is compensated
+        let scale = 4. / WINDOW as f32;
+        let magnitudes: Vec<f32> = buffer[..WINDOW / 2]
+            .iter()
+            .map(|bin| bin.norm() * scale)
+            .collect();
+        let resolution = sample_rate / WINDOW as f32;
+        let (low, high) = (FREQUENCY_RANGE.0, FREQUENCY_RANGE.1.min(sample_rate / 2.));
+        let edge = |band: usize| low * (high / low).powf(band as f32 / bands as f32) / resolution;
+        (0..bands)
+            .map(|band| {
+                let (start, end) = (edge(band), edge(band + 1));
+                // Narrow low bands fall between two bins and use the closest one
+                let bins = (start.round() as usize).max(1)..=(end.round() as usize).max(1);
+                let magnitude = magnitudes
+                    .get(bins)
+                    .map(|bins| bins.iter().copied().fold(0., f32::max))
+                    .unwrap_or_default();
+                let db = 20. * magnitude.max(f32::MIN_POSITIVE).log10();
+                ((db - FLOOR) / -FLOOR).clamp(0., 1.)
+            })
+            .collect()
+    }
+}
+
+pub struct Tap<I> {
+    input: I,
+    state: Arc<Mutex<AnalyzerState>>,
+    generation: u64,
+    buffer: Vec<f32>,
+    #[doc = "Sum of the samples of the frame being read"]
+    frame: f32,
+    channel: u16,
+}
+
+impl<I: Source> Tap<I> {
+    fn flush(&mut self) {
+        if let Ok(mut state) = self.state.try_lock()
+            && state.generation == self.generation
+        {
+            state.sample_rate = self.input.sample_rate();
+            state.samples.extend(self.buffer.iter());
+            let excess = state.samples.len().saturating_sub(WINDOW);
+            state.samples.drain(..excess);
+        }
+        self.buffer.clear();
+    }
+}
+
+impl<I: Source> Iterator for Tap<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1);
+        self.frame += sample;
+        self.channel += 1;
+        if self.channel >= channels {
+            self.buffer.push(self.frame / channels as f32);
+            (self.frame, self.channel) = (0., 0);
+            (self.buffer.len() >= CHECK_INTERVAL).then(|| self.flush());
+        }
+        Some(sample)
+    }
+}
+
+impl<I: Source> Source for Tap<I> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.input.try_seek(pos)
+    }
+}
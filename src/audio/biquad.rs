@@ -0,0 +1,215 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+#[doc = "Normalized biquad coefficients `[b0, b1, b2, a1, a2]`"]
+pub type Coefficients = [f32; 5];
+
+fn normalize(b: [f32; 3], a: [f32; 3]) -> Coefficients {
+    [
+        b[0] / a[0],
+        b[1] / a[0],
+        b[2] / a[0],
+        a[1] / a[0],
+        a[2] / a[0],
+    ]
+}
+
+#[doc = "RBJ cookbook peaking filter, `gain` in dB"]
+pub fn peaking(frequency: f32, gain: f32, q: f32, sample_rate: f32) -> Coefficients {
+    let a = 10f32.powf(gain / 40.);
+    let w0 = 2. * PI * frequency / sample_rate;
+    let alpha = w0.sin() / (2. * q.max(0.1));
+    let cos = w0.cos();
+    normalize(
+        [1. + alpha * a, -2. * cos, 1. - alpha * a],
+        [1. + alpha / a, -2. * cos, 1. - alpha / a],
+    )
+}
+
+#[doc = "RBJ cookbook low-pass filter"]
+pub fn low_pass(frequency: f32, q: f32, sample_rate: f32) -> Coefficients {
+    let w0 = 2. * PI * frequency / sample_rate;
+    let alpha = w0.sin() / (2. * q.max(0.1));
+    let cos = w0.cos();
+    normalize(
+        [(1. - cos) / 2., 1. - cos, (1. - cos) / 2.],
+        [1. + alpha, -2. * cos, 1. - alpha],
+    )
+}
+
+#[doc = "RBJ cookbook high-pass filter"]
+pub fn high_pass(frequency: f32, q: f32, sample_rate: f32) -> Coefficients {
+    let w0 = 2. * PI * frequency / sample_rate;
+    let alpha = w0.sin() / (2. * q.max(0.1));
+    let cos = w0.cos();
+    normalize(
+        [(1. + cos) / 2., -(1. + cos), (1. + cos) / 2.],
+        [1. + alpha, -2. * cos, 1. - alpha],
+    )
+}
+
+#[doc = "Settings turned into a chain of biquads, an empty chain lets the samples through untouched"]
+pub trait Design {
+    fn design(&self, sample_rate: f32) -> Vec<Coefficients>;
+}
+
+#[derive(Debug, Default)]
+struct Versioned<S> {
+    settings: S,
+    #[doc = "Bumped on every change so sources know when to recompute their coefficients"]
+    version: u64,
+}
+
+#[doc = "Handle on settings shared by every source it wrapped, changes apply while playing"]
+#[derive(Debug, Default)]
+pub struct Shared<S> {
+    state: Arc<Mutex<Versioned<S>>>,
+}
+
+impl<S> Clone for Shared<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S: Design + Clone + Default> Shared<S> {
+    pub fn wrap<I: Source>(&self, input: I) -> BiquadSource<I, S> {
+        BiquadSource {
+            input,
+            state: self.state.clone(),
+            version: None,
+            filters: Vec::new(),
+            channel: 0,
+            until_check: 0,
+        }
+    }
+    pub fn set(&self, settings: S) {
+        if let Ok(mut state) = self.state.lock() {
+            state.settings = settings;
+            state.version += 1;
+        }
+    }
+    pub fn settings(&self) -> S {
+        self.state
+            .lock()
+            .map(|state| state.settings.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[doc = "One biquad with the history of every channel"]
+pub(super) struct Filter {
+    pub(super) coefficients: Coefficients,
+    #[doc = "`[x1, x2, y1, y2]` per channel"]
+    history: Vec<[f32; 4]>,
+}
+
+impl Filter {
+    pub(super) fn new(coefficients: Coefficients, channels: usize) -> Self {
+        Self {
+            coefficients,
+            history: vec![[0.; 4]; channels],
+        }
+    }
+    pub(super) fn process(&mut self, channel: usize, x: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let Some(h) = self.history.get_mut(channel) else {
+            return x;
+        };
+        let y = b0 * x + b1 * h[0] + b2 * h[1] - a1 * h[2] - a2 * h[3];
+        *h = [x, h[0], y, h[2]];
+        y
+    }
+}
+
+#[doc = "Samples processed between two reads of the shared settings"]
+const CHECK_INTERVAL: usize = 256;
+
+pub struct BiquadSource<I, S> {
+    input: I,
+    state: Arc<Mutex<Versioned<S>>>,
+    #[doc = "Version of the settings and format the filters were computed for"]
+    version: Option<(u64, u32, u16)>,
+    filters: Vec<Filter>,
+    channel: usize,
+    until_check: usize,
+}
+
+impl<I: Source, S: Design> BiquadSource<I, S> {
+    fn poll_state(&mut self) {
+        let Ok(state) = self.state.try_lock() else {
+            return;
+        };
+        let (sample_rate, channels) = (self.input.sample_rate(), self.input.channels());
+        let version = Some((state.version, sample_rate, channels));
+        if self.version == version {
+            return;
+        }
+        self.version = version;
+        let previous = std::mem::take(&mut self.filters);
+        self.filters = state
+            .settings
+            .design(sample_rate as f32)
+            .into_iter()
+            .enumerate()
+            .map(|(index, coefficients)| Filter {
+                coefficients,
+                // Keeping the history avoids a click when only a setting changed
+                history: previous
+                    .get(index)
+                    .map(|filter| filter.history.clone())
+                    .filter(|history| history.len() == channels as usize)
+                    .unwrap_or_else(|| vec![[0.; 4]; channels as usize]),
+            })
+            .collect();
+    }
+}
+
+impl<I: Source, S: Design> Iterator for BiquadSource<I, S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_check == 0 {
+            self.poll_state();
+            self.until_check = CHECK_INTERVAL;
+        }
+        self.until_check -= 1;
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.input.channels().max(1) as usize;
+        Some(
+            self.filters
+                .iter_mut()
+                .fold(sample, |sample, filter| filter.process(channel, sample)),
+        )
+    }
+}
+
+impl<I: Source, S: Design> Source for BiquadSource<I, S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.input.try_seek(pos)?;
+        self.filters
+            .iter_mut()
+            .for_each(|filter| filter.history.fill([0.; 4]));
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use lofty::{config::ParseOptions, file::TaggedFileExt, tag::ItemKey};
+
+use super::probe::{has_audio_extension, verify_audio_file};
+use crate::error::{Error, Result};
+
+#[doc = "Frames per second of the CUE sheet timestamps"]
+const FRAMES_PER_SECOND: f64 = 75.;
+
+#[doc = "Track of a CUE sheet, a segment of the file it belongs to"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    #[doc = "Position of `INDEX 01` in the file"]
+    pub start: Duration,
+    #[doc = "Start of the next track of the same file, None for the last one which plays to the end"]
+    pub end: Option<Duration>,
+}
+
+#[doc = "Entry of the queue, a whole file or one track of a CUE sheet"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub path: PathBuf,
+    pub cue: Option<CueTrack>,
+}
+
+impl From<PathBuf> for Track {
+    fn from(path: PathBuf) -> Self {
+        Self { path, cue: None }
+    }
+}
+
+impl Track {
+    #[doc = "Position of the start of the track in its file"]
+    pub fn start(&self) -> Duration {
+        self.cue.as_ref().map_or(Duration::ZERO, |cue| cue.start)
+    }
+    #[doc = "Position of the end of the track in its file, None when it plays to the end of the file"]
+    pub fn end(&self) -> Option<Duration> {
+        self.cue.as_ref().and_then(|cue| cue.end)
+    }
+    #[doc = "`NN. Title` of a CUE track, the file name otherwise"]
+    pub fn name(&self) -> String {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match &self.cue {
+            Some(cue) => format!(
+                "{:02}. {}",
+                cue.number,
+                cue.title.clone().unwrap_or(file_name)
+            ),
+            None => file_name,
+        }
+    }
+}
+
+pub fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"))
+}
+
+#[doc = "Argument of a CUE command, unquoted when it is quoted"]
+fn argument(rest: &str) -> String {
+    let rest = rest.trim();
+    match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default().to_string(),
+        None => rest
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+#[doc = "`MM:SS:FF` timestamp, FF being frames of 1/75 second"]
+fn timestamp(value: &str) -> Option<Duration> {
+    let mut parts = value.trim().split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    let seconds = minutes.checked_mul(60)?.checked_add(seconds)?;
+    Some(Duration::from_secs_f64(
+        seconds as f64 + frames as f64 / FRAMES_PER_SECOND,
+    ))
+}
+
+#[doc = "Tracks of a CUE sheet with the `FILE` they belong to, the ends are filled from the next track of the same file"]
+fn parse(content: &str) -> Vec<(String, CueTrack)> {
+    let content = content.trim_start_matches('\u{feff}');
+    let mut album_performer = None;
+    let mut file = String::new();
+    // INDEX 00 and INDEX 01 of every track, the pregap is only used when there is no INDEX 01
+    let mut tracks: Vec<(String, CueTrack, [Option<Duration>; 2])> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let command = command.to_uppercase();
+        let track = tracks
+            .last_mut()
+            .filter(|(track_file, _, _)| *track_file == file);
+        match (command.as_str(), track) {
+            ("FILE", _) => file = argument(rest),
+            ("TRACK", _) => {
+                let number = argument(rest).parse().unwrap_or(tracks.len() as u32 + 1);
+                tracks.push((
+                    file.clone(),
+                    CueTrack {
+                        number,
+                        title: None,
+                        performer: album_performer.clone(),
+                        start: Duration::ZERO,
+                        end: None,
+                    },
+                    [None; 2],
+                ));
+            }
+            ("TITLE", Some((_, track, _))) => {
+                track.title = Some(argument(rest)).filter(|value| !value.is_empty())
+            }
+            ("PERFORMER", Some((_, track, _))) => {
+                track.performer = Some(argument(rest)).filter(|value| !value.is_empty())
+            }
+            ("PERFORMER", None) => {
+                album_performer = Some(argument(rest)).filter(|value| !value.is_empty())
+            }
+            ("INDEX", Some((_, _, indices))) => {
+                let mut arguments = rest.split_whitespace();
+                if let (Some(index @ (0 | 1)), Some(position)) = (
+                    arguments
+                        .next()
+                        .and_then(|index| index.parse::<usize>().ok()),
+                    arguments.next().and_then(timestamp),
+                ) {
+                    indices[index] = Some(position);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut tracks: Vec<(String, CueTrack)> = tracks
+        .into_iter()
+        .filter_map(|(file, mut track, [pregap, start])| {
+            track.start = start.or(pregap)?;
+            Some((file, track))
+        })
+        .collect();
+    let ends: Vec<Option<Duration>> = tracks
+        .iter()
+        .enumerate()
+        .map(|(index, (file, _))| {
+            tracks
+                .get(index + 1)
+                .filter(|(next_file, _)| next_file == file)
+                .map(|(_, next)| next.start)
+        })
+        .collect();
+    tracks
+        .iter_mut()
+        .zip(ends)
+        .for_each(|((_, track), end)| track.end = end);
+    tracks
+}
+
+#[doc = "File a `FILE` entry of a CUE sheet in `dir` refers to, a playable file with the same name but another extension is accepted since rips are often converted after the sheet was written"]
+fn resolve(dir: &Path, file: &str) -> Option<PathBuf> {
+    let path = dir.join(file);
+    if path.is_file() {
+        return Some(path);
+    }
+    let stem = path.file_stem()?.to_os_string();
+    let mut candidates: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|other| other.file_stem() == Some(stem.as_os_str()) && !is_cue_sheet(other))
+        .filter_map(|other| verify_audio_file(&other))
+        .collect();
+    // Known audio extensions first, then by name since the directory order is unspecified
+    candidates.sort_by_key(|candidate| (!has_audio_extension(candidate), candidate.clone()));
+    candidates.into_iter().next()
+}
+
+#[doc = "Tracks of a CUE sheet file, those whose file cannot be found are left out"]
+pub fn read_cue_sheet(path: &Path) -> Result<Vec<Track>> {
+    let bytes = std::fs::read(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    let content = String::from_utf8_lossy(&bytes);
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Ok(parse(&content)
+        .into_iter()
+        .filter_map(|(file, cue)| {
+            Some(Track {
+                path: resolve(dir, &file)?,
+                cue: Some(cue),
+            })
+        })
+        .collect())
+}
+
+#[doc = "Extensions of the formats whose tags can hold a CUE sheet, WavPack and Monkey's Audio can too but cannot be played"]
+const EMBEDDING: [&str; 1] = ["flac"];
+
+#[doc = "Tracks of the CUE sheet embedded in the `CUESHEET` comment of a file, its `FILE` entries are ignored"]
+fn embedded_cue_sheet(path: &Path) -> Option<Vec<Track>> {
+    let extension = path.extension()?;
+    if !EMBEDDING
+        .iter()
+        .any(|embedding| extension.eq_ignore_ascii_case(embedding))
+    {
+        return None;
+    }
+    // Only the tags are needed, the stream properties and pictures are skipped
+    let tagged_file = lofty::probe::Probe::open(path)
+        .ok()?
+        .options(
+            ParseOptions::new()
+                .read_properties(false)
+                .read_cover_art(false),
+        )
+        .read()
+        .ok()?;
+    let content = tagged_file.tags().iter().find_map(|tag| {
+        tag.items().find_map(|item| match item.key() {
+            ItemKey::Unknown(key) if key.eq_ignore_ascii_case("cuesheet") => {
+                item.value().text().map(str::to_string)
+            }
+            _ => None,
+        })
+    })?;
+    let tracks: Vec<Track> = parse(&content)
+        .into_iter()
+        .map(|(_, cue)| Track {
+            path: path.to_path_buf(),
+            cue: Some(cue),
+        })
+        .collect();
+    // Tracks from several files cannot all belong to this one
+    let ends = tracks.iter().filter(|track| track.end().is_none()).count();
+    (!tracks.is_empty() && ends == 1).then_some(tracks)
+}
+
+#[doc = "Replace every CUE sheet by its tracks and every file described by a CUE sheet, embedded or next to it, by the tracks of the sheet"]
+pub fn expand(paths: Vec<PathBuf>) -> Vec<Track> {
+    // Sheets found next to the files, read once per directory
+    let mut sheets: HashMap<PathBuf, Vec<Track>> = HashMap::new();
+    let mut tracks = Vec::with_capacity(paths.len());
+    for path in paths {
+        if is_cue_sheet(&path) {
+            tracks.extend(read_cue_sheet(&path).unwrap_or_default());
+            continue;
+        }
+        if let Some(embedded) = embedded_cue_sheet(&path) {
+            tracks.extend(embedded);
+            continue;
+        }
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let sheet = sheets.entry(dir.clone()).or_insert_with(|| {
+            std::fs::read_dir(&dir)
+                .map(|entries| {
+                    let mut sheets: Vec<PathBuf> = entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| is_cue_sheet(path))
+                        .collect();
+                    sheets.sort();
+                    sheets
+                        .iter()
+                        .flat_map(|sheet| read_cue_sheet(sheet).unwrap_or_default())
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+        let described: Vec<Track> = sheet
+            .iter()
+            .filter(|track| track.path.file_name() == path.file_name())
+            .cloned()
+            .collect();
+        if described.is_empty() {
+            tracks.push(Track::from(path));
+        } else {
+            tracks.extend(described);
+        }
+    }
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(number: u32, start: f64, end: Option<f64>) -> CueTrack {
+        CueTrack {
+            number,
+            title: None,
+            performer: None,
+            start: Duration::from_secs_f64(start),
+            end: end.map(Duration::from_secs_f64),
+        }
+    }
+
+    #[test]
+    fn timestamp_reads_minutes_seconds_and_frames() {
+        assert_eq!(timestamp("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(timestamp("01:02:00"), Some(Duration::from_secs(62)));
+        assert_eq!(timestamp(" 100:00:75 "), Some(Duration::from_secs(6001)));
+    }
+
+    #[test]
+    fn timestamp_rejects_malformed_values() {
+        for value in [
+            "",
+            "01:02",
+            "aa:00:00",
+            "01:-2:00",
+            "01:02:x",
+            "::",
+            "99999999999999999999:00:00",
+            "307445734561825861:00:00",
+        ] {
+            assert_eq!(timestamp(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn index_01_is_preferred_to_the_pregap() {
+        let tracks = parse(
+            "FILE \"album.flac\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             INDEX 00 01:00:00\n\
+             INDEX 01 01:02:00\n\
+             TRACK 03 AUDIO\n\
+             INDEX 00 02:00:00\n",
+        );
+        let tracks: Vec<CueTrack> = tracks.into_iter().map(|(_, track)| track).collect();
+        assert_eq!(
+            tracks,
+            vec![
+                track(1, 0., Some(62.)),
+                track(2, 62., Some(120.)),
+                track(3, 120., None)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_last_track_of_every_file_has_no_end() {
+        let tracks = parse(
+            "PERFORMER \"Band\"\n\
+             FILE \"one.flac\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             TITLE \"First\"\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             PERFORMER \"Guest\"\n\
+             INDEX 01 03:00:00\n\
+             FILE \"two.flac\" WAVE\n\
+             TRACK 03 AUDIO\n\
+             INDEX 01 00:00:00\n\
+             TRACK 04 AUDIO\n\
+             INDEX 01 04:00:00\n",
+        );
+        let files: Vec<&str> = tracks.iter().map(|(file, _)| file.as_str()).collect();
+        assert_eq!(files, ["one.flac", "one.flac", "two.flac", "two.flac"]);
+        let ends: Vec<Option<Duration>> = tracks.iter().map(|(_, track)| track.end).collect();
+        assert_eq!(
+            ends,
+            [
+                Some(Duration::from_secs(180)),
+                None,
+                Some(Duration::from_secs(240)),
+                None
+            ]
+        );
+        assert_eq!(tracks[0].1.title.as_deref(), Some("First"));
+        assert_eq!(tracks[0].1.performer.as_deref(), Some("Band"));
+        assert_eq!(tracks[1].1.performer.as_deref(), Some("Guest"));
+    }
+
+    #[test]
+    fn a_leading_byte_order_mark_is_skipped() {
+        let tracks = parse("\u{feff}FILE \"album.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n");
+        assert_eq!(tracks, vec![("album.wav".to_string(), track(1, 0., None))]);
+    }
+
+    #[test]
+    fn tracks_with_malformed_indices_are_left_out() {
+        let tracks = parse(
+            "FILE \"album.wav\" WAVE\n\
+             TRACK 01 AUDIO\n\
+             INDEX 01 00:00\n\
+             TRACK 02 AUDIO\n\
+             INDEX 01 00:10:00\n",
+        );
+        assert_eq!(tracks, vec![("album.wav".to_string(), track(2, 10., None))]);
+    }
+
+    #[test]
+    fn a_missing_file_resolves_to_a_playable_file_of_the_same_name() {
+        let dir = std::env::temp_dir().join(format!("cue-{}-resolve", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for other in ["album.log", "album.jpg", "album.nfo", "album.cue"] {
+            std::fs::write(dir.join(other), "not audio").unwrap();
+        }
+        assert_eq!(resolve(&dir, "album.flac"), None);
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.join("album.wav"), spec).unwrap();
+        (0..800).for_each(|_| writer.write_sample(0i16).unwrap());
+        writer.finalize().unwrap();
+        assert_eq!(resolve(&dir, "album.flac"), Some(dir.join("album.wav")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
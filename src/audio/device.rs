@@ -0,0 +1,136 @@
+use rodio::cpal::{
+    self,
+    traits::{DeviceTrait, HostTrait},
+};
+
+use crate::error::{Error, Result};
+
+#[doc = "Output device of one of the available audio hosts"]
+#[derive(Clone)]
+pub struct OutputDevice {
+    pub host: String,
+    pub name: String,
+    pub device: cpal::Device,
+}
+
+impl std::fmt::Debug for OutputDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputDevice")
+            .field("host", &self.host)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl OutputDevice {
+    #[doc = "Supported output configurations as `channels sample_rate_range sample_format`"]
+    pub fn formats(&self) -> Vec<String> {
+        self.device
+            .supported_output_configs()
+            .map(|configs| {
+                configs
+                    .map(|config| {
+                        let (min, max) = (config.min_sample_rate().0, config.max_sample_rate().0);
+                        let rates = if min == max {
+                            format!("{min}Hz")
+                        } else {
+                            format!("{min}-{max}Hz")
+                        };
+                        format!("{}ch {rates} {}", config.channels(), config.sample_format())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.host, self.name)
+    }
+}
+
+#[doc = "How a player picks its output device"]
+#[derive(Debug, Clone, Copy)]
+pub enum DeviceSelector<'a> {
+    #[doc = "Index, exact name or part of a name, as given on the command line or in the config"]
+    Query(&'a str),
+    #[doc = "Exact name, for the device of a previous player"]
+    Name(&'a str),
+}
+
+impl DeviceSelector<'_> {
+    pub fn find(self) -> Result<OutputDevice> {
+        match self {
+            DeviceSelector::Query(selector) => find_device(selector),
+            DeviceSelector::Name(name) => output_devices()
+                .into_iter()
+                .find(|device| device.name == name)
+                .ok_or(Error::DeviceNotFound(name.to_string())),
+        }
+    }
+}
+
+#[doc = "Every output device of every available host, in the order used by `--device <index>`"]
+pub fn output_devices() -> Vec<OutputDevice> {
+    cpal::available_hosts()
+        .into_iter()
+        .filter_map(|id| cpal::host_from_id(id).ok())
+        .flat_map(|host| {
+            let host_name = host.id().name().to_string();
+            host.output_devices()
+                .map(|devices| devices.collect::<Vec<_>>())
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |device| OutputDevice {
+                    host: host_name.clone(),
+                    name: device.name().unwrap_or_default(),
+                    device,
+                })
+        })
+        .collect()
+}
+
+pub fn default_device() -> Result<cpal::Device> {
+    cpal::default_host()
+        .default_output_device()
+        .ok_or(Error::NoDevice)
+}
+
+#[doc = "Find a device by its index in `output_devices`, its exact name or a case-insensitive part of its name"]
+fn find_device(selector: &str) -> Result<OutputDevice> {
+    let devices = output_devices();
+    let found = match selector.parse::<usize>() {
+        Ok(index) => devices.get(index).cloned(),
+        Err(_) => {
+            let lower = selector.to_lowercase();
+            devices
+                .iter()
+                .find(|device| device.name == selector)
+                .or(devices
+                    .iter()
+                    .find(|device| device.name.to_lowercase().contains(&lower)))
+                .cloned()
+        }
+    };
+    found.ok_or(Error::DeviceNotFound(selector.to_string()))
+}
+
+#[doc = "Print the `devices` subcommand listing"]
+pub fn print_devices() {
+    let default_name = default_device().ok().and_then(|device| device.name().ok());
+    let devices = output_devices();
+    devices
+        .is_empty()
+        .then(|| println!("[!] No output device found"));
+    let mut host = String::new();
+    for (index, device) in devices.iter().enumerate() {
+        if device.host != host {
+            host = device.host.clone();
+            println!("[Host] {host}");
+        }
+        let default = (Some(&device.name) == default_name.as_ref()).then_some(" (default)");
+        println!("  {index}: {}{}", device.name, default.unwrap_or_default());
+        device
+            .formats()
+            .iter()
+            .for_each(|format| println!("      {format}"));
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use super::biquad::{self, Coefficients, Design, Shared};
+
+#[doc = "Center frequencies of the 10 band graphic equalizer"]
+const GRAPHIC_FREQUENCIES: [f32; 10] = [
+    31., 62., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.,
+];
+
+#[doc = "Q of one octave wide bands"]
+const GRAPHIC_Q: f32 = 1.41;
+
+#[doc = "Gain limit in dB of a band"]
+pub const MAX_GAIN: f32 = 12.;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EqPreset {
+    Flat,
+    BassBoost,
+    Vocal,
+    Loudness,
+}
+
+impl EqPreset {
+    const ALL: [EqPreset; 4] = [
+        EqPreset::Flat,
+        EqPreset::BassBoost,
+        EqPreset::Vocal,
+        EqPreset::Loudness,
+    ];
+    fn gains(&self) -> [f32; 10] {
+        match self {
+            EqPreset::Flat => [0.; 10],
+            EqPreset::BassBoost => [6., 5., 4., 2., 0., 0., 0., 0., 0., 0.],
+            EqPreset::Vocal => [-2., -2., -1., 0., 2., 4., 4., 2., 0., -1.],
+            EqPreset::Loudness => [6., 4., 2., 0., -1., -1., 0., 2., 4., 5.],
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            EqPreset::Flat => "Flat",
+            EqPreset::BassBoost => "Bass Boost",
+            EqPreset::Vocal => "Vocal",
+            EqPreset::Loudness => "Loudness",
+        }
+    }
+    pub fn next(&self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|preset| preset == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[doc = "Peaking filter centered on `frequency` Hz, `gain` in dB"]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Band {
+    pub frequency: f32,
+    pub gain: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+}
+
+fn default_q() -> f32 {
+    GRAPHIC_Q
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EqSettings {
+    pub enabled: bool,
+    pub bands: Vec<Band>,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self::from_preset(EqPreset::Flat)
+    }
+}
+
+impl EqSettings {
+    #[doc = "10 band graphic equalizer set to a preset"]
+    pub fn from_preset(preset: EqPreset) -> Self {
+        Self {
+            enabled: true,
+            bands: GRAPHIC_FREQUENCIES
+                .iter()
+                .zip(preset.gains())
+                .map(|(frequency, gain)| Band {
+                    frequency: *frequency,
+                    gain,
+                    q: GRAPHIC_Q,
+                })
+                .collect(),
+        }
+    }
+    #[doc = "Preset matching the current bands, None once a band was edited or with custom bands"]
+    pub fn preset(&self) -> Option<EqPreset> {
+        EqPreset::ALL
+            .into_iter()
+            .find(|preset| Self::from_preset(*preset).bands == self.bands)
+    }
+    pub fn adjust_gain(&mut self, band: usize, delta: f32) {
+        if let Some(band) = self.bands.get_mut(band) {
+            band.gain = (band.gain + delta).clamp(-MAX_GAIN, MAX_GAIN);
+        }
+    }
+}
+
+impl Design for EqSettings {
+    fn design(&self, sample_rate: f32) -> Vec<Coefficients> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        self.bands
+            .iter()
+            .filter(|band| {
+                band.gain != 0. && band.frequency > 0. && band.frequency < sample_rate / 2.
+            })
+            .map(|band| biquad::peaking(band.frequency, band.gain, band.q, sample_rate))
+            .collect()
+    }
+}
+
+#[doc = "Handle on the equalizer settings shared by every source it wrapped"]
+pub type Equalizer = Shared<EqSettings>;
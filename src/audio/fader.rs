@@ -0,0 +1,163 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    EqualPower,
+}
+
+impl FadeCurve {
+    #[doc = "Gain of a track fading in, `t` going from 0 to 1"]
+    fn gain(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).sin(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossfade {
+    pub duration: Duration,
+    pub curve: FadeCurve,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ramp {
+    fade_in: bool,
+    duration: Duration,
+    curve: FadeCurve,
+}
+
+#[derive(Debug, Default)]
+struct FaderState {
+    pending: Option<Ramp>,
+    done: bool,
+}
+
+#[doc = "Handle on the gain applied to everything a sink outputs"]
+#[derive(Debug, Clone, Default)]
+pub struct Fader {
+    state: Arc<Mutex<FaderState>>,
+}
+
+impl Fader {
+    #[doc = "Wrap the output of a sink, the source starts silent when `fade_in` is provided"]
+    pub fn wrap<I: Source>(&self, input: I, fade_in: Option<Crossfade>) -> FaderSource<I> {
+        let mut source = FaderSource {
+            input,
+            state: self.state.clone(),
+            ramp: None,
+            elapsed: 0,
+            total: 0,
+            gain: 1.,
+            until_check: 0,
+        };
+        if let Some(fade) = fade_in {
+            self.start(true, fade);
+            source.gain = 0.;
+        }
+        source
+    }
+    pub fn fade_out(&self, fade: Crossfade) {
+        self.start(false, fade);
+    }
+    fn start(&self, fade_in: bool, fade: Crossfade) {
+        if let Ok(mut state) = self.state.lock() {
+            state.done = false;
+            state.pending = Some(Ramp {
+                fade_in,
+                duration: fade.duration,
+                curve: fade.curve,
+            });
+        }
+    }
+    #[doc = "True once the last requested ramp reached its end"]
+    pub fn is_done(&self) -> bool {
+        self.state.lock().map(|state| state.done).unwrap_or(true)
+    }
+}
+
+#[doc = "Samples processed between two reads of the shared fader state"]
+const CHECK_INTERVAL: usize = 256;
+
+pub struct FaderSource<I> {
+    input: I,
+    state: Arc<Mutex<FaderState>>,
+    ramp: Option<Ramp>,
+    elapsed: usize,
+    total: usize,
+    gain: f32,
+    until_check: usize,
+}
+
+impl<I: Source> FaderSource<I> {
+    fn poll_state(&mut self) {
+        let Ok(mut state) = self.state.try_lock() else {
+            return;
+        };
+        if let Some(ramp) = state.pending.take() {
+            self.total = (ramp.duration.as_secs_f32()
+                * self.input.sample_rate() as f32
+                * self.input.channels() as f32) as usize;
+            self.elapsed = 0;
+            self.ramp = Some(ramp);
+        }
+        if self.ramp.is_none() {
+            state.done = true;
+        }
+    }
+}
+
+impl<I: Source> Iterator for FaderSource<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_check == 0 {
+            self.poll_state();
+            self.until_check = CHECK_INTERVAL;
+        }
+        self.until_check -= 1;
+        if let Some(ramp) = self.ramp {
+            let t = if self.total == 0 {
+                1.
+            } else {
+                self.elapsed as f32 / self.total as f32
+            };
+            self.gain = if ramp.fade_in {
+                ramp.curve.gain(t)
+            } else {
+                ramp.curve.gain(1. - t)
+            };
+            self.elapsed += 1;
+            (self.elapsed >= self.total).then(|| self.ramp = None);
+        }
+        self.input.next().map(|sample| sample * self.gain)
+    }
+}
+
+impl<I: Source> Source for FaderSource<I> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,72 @@
+use super::biquad::{self, Coefficients, Design, Shared};
+
+#[doc = "Q of the low-pass and high-pass filters, the one rodio uses for its own"]
+const PASS_Q: f32 = 0.5;
+
+#[doc = "Cutoff range the filters can be set to in Hz"]
+const CUTOFF_RANGE: (u32, u32) = (20, 20000);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PassFilter {
+    pub enabled: bool,
+    pub frequency: u32,
+}
+
+impl PassFilter {
+    fn from(cutoff: Option<u32>, default: u32) -> Self {
+        Self {
+            enabled: cutoff.is_some(),
+            frequency: cutoff.unwrap_or(default),
+        }
+    }
+    pub fn cutoff(&self) -> Option<u32> {
+        self.enabled.then_some(self.frequency)
+    }
+    #[doc = "Multiply the cutoff by `factor`, staying in `CUTOFF_RANGE`"]
+    pub fn scale(&mut self, factor: f32) {
+        self.frequency =
+            ((self.frequency as f32 * factor).round() as u32).clamp(CUTOFF_RANGE.0, CUTOFF_RANGE.1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSettings {
+    pub low_pass: PassFilter,
+    pub high_pass: PassFilter,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self::from(None, None)
+    }
+}
+
+impl FilterSettings {
+    #[doc = "Filters enabled for the provided cutoffs, the disabled ones keep a usable default cutoff"]
+    pub fn from(low_pass: Option<u32>, high_pass: Option<u32>) -> Self {
+        Self {
+            low_pass: PassFilter::from(low_pass, 8000),
+            high_pass: PassFilter::from(high_pass, 80),
+        }
+    }
+}
+
+impl Design for FilterSettings {
+    fn design(&self, sample_rate: f32) -> Vec<Coefficients> {
+        let usable = |frequency: &u32| (*frequency as f32) < sample_rate / 2.;
+        let low_pass = self
+            .low_pass
+            .cutoff()
+            .filter(usable)
+            .map(|frequency| biquad::low_pass(frequency as f32, PASS_Q, sample_rate));
+        let high_pass = self
+            .high_pass
+            .cutoff()
+            .filter(usable)
+            .map(|frequency| biquad::high_pass(frequency as f32, PASS_Q, sample_rate));
+        low_pass.into_iter().chain(high_pass).collect()
+    }
+}
+
+#[doc = "Handle on the low-pass and high-pass filters shared by every source it wrapped"]
+pub type Filters = Shared<FilterSettings>;
@@ -0,0 +1,264 @@
+use std::{f64::consts::PI, fs::File, io::BufReader, path::PathBuf};
+
+use rodio::Source;
+
+use crate::error::{Error, Result};
+
+#[doc = "Loudness ReplayGain 2.0 normalizes to in LUFS"]
+pub const REFERENCE: f64 = -18.;
+
+#[doc = "Blocks quieter than this are never part of a measurement in LUFS"]
+const ABSOLUTE_GATE: f64 = -70.;
+
+#[doc = "Gate below the ungated integrated loudness in LU"]
+const RELATIVE_GATE: f64 = -10.;
+
+#[doc = "Gate below the ungated short-term loudness in LU used by the loudness range"]
+const RANGE_GATE: f64 = -20.;
+
+#[doc = "Oversampling of the true peak meter"]
+const OVERSAMPLING: usize = 4;
+
+#[doc = "Taps of each phase of the true peak interpolation filter"]
+const PHASE_TAPS: usize = 12;
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10. * power.log10()
+}
+
+fn power(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.)
+}
+
+#[doc = "Channel weights of BS.1770, the LFE of a 5.1 stream is ignored and the surround channels boosted"]
+fn channel_weights(channels: usize) -> Vec<f64> {
+    match channels {
+        5 => vec![1., 1., 1., 1.41, 1.41],
+        6 => vec![1., 1., 1., 0., 1.41, 1.41],
+        _ => vec![1.; channels],
+    }
+}
+
+#[doc = "Biquad in f64, `[b0, b1, b2, a1, a2]` with a history per channel"]
+struct Stage {
+    coefficients: [f64; 5],
+    history: Vec<[f64; 4]>,
+}
+
+impl Stage {
+    fn new(coefficients: [f64; 5], channels: usize) -> Self {
+        Self {
+            coefficients,
+            history: vec![[0.; 4]; channels],
+        }
+    }
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let h = &mut self.history[channel];
+        let y = b0 * x + b1 * h[0] + b2 * h[1] - a1 * h[2] - a2 * h[3];
+        *h = [x, h[0], y, h[2]];
+        y
+    }
+}
+
+#[doc = "The two stages of the K-weighting filter, a high shelf modelling the head and the RLB high-pass"]
+fn k_weighting(sample_rate: f64, channels: usize) -> [Stage; 2] {
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1. + k / q + k * k;
+    let shelf = [
+        (vh + vb * k / q + k * k) / a0,
+        2. * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+        2. * (k * k - 1.) / a0,
+        (1. - k / q + k * k) / a0,
+    ];
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1. + k / q + k * k;
+    let high_pass = [
+        1.,
+        -2.,
+        1.,
+        2. * (k * k - 1.) / a0,
+        (1. - k / q + k * k) / a0,
+    ];
+    [Stage::new(shelf, channels), Stage::new(high_pass, channels)]
+}
+
+#[doc = "Peak of the signal oversampled by a windowed sinc, catching the peaks between two samples"]
+struct TruePeak {
+    #[doc = "Interpolation filter split in one set of taps per phase"]
+    phases: Vec<Vec<f64>>,
+    #[doc = "Last `PHASE_TAPS` samples per channel, most recent first"]
+    history: Vec<Vec<f64>>,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new(channels: usize) -> Self {
+        let length = OVERSAMPLING * PHASE_TAPS;
+        let center = (length - 1) as f64 / 2.;
+        let taps: Vec<f64> = (0..length)
+            .map(|n| {
+                let x = (n as f64 - center) / OVERSAMPLING as f64;
+                let sinc = if x == 0. {
+                    1.
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.5 - 0.5 * (2. * PI * n as f64 / (length - 1) as f64).cos();
+                sinc * window
+            })
+            .collect();
+        Self {
+            phases: (0..OVERSAMPLING)
+                .map(|phase| {
+                    taps.iter()
+                        .skip(phase)
+                        .step_by(OVERSAMPLING)
+                        .copied()
+                        .collect()
+                })
+                .collect(),
+            history: vec![vec![0.; PHASE_TAPS]; channels],
+            peak: 0.,
+        }
+    }
+    fn process(&mut self, channel: usize, x: f64) {
+        let history = &mut self.history[channel];
+        history.rotate_right(1);
+        history[0] = x;
+        self.peak = self
+            .phases
+            .iter()
+            .fold(self.peak.max(x.abs()), |peak, taps| {
+                let y: f64 = taps.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                peak.max(y.abs())
+            });
+    }
+}
+
+#[doc = "EBU R128 measurement of a file"]
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub path: PathBuf,
+    #[doc = "Integrated loudness in LUFS, None for a silent file"]
+    pub integrated: Option<f64>,
+    #[doc = "Loudness range in LU"]
+    pub range: f64,
+    #[doc = "Linear true peak"]
+    pub true_peak: f64,
+    #[doc = "Power of the 400 ms blocks, kept to measure an album"]
+    blocks: Vec<f64>,
+}
+
+impl Measurement {
+    #[doc = "ReplayGain 2.0 gain in dB bringing the file to `REFERENCE`"]
+    pub fn gain(&self) -> Option<f64> {
+        self.integrated.map(|integrated| REFERENCE - integrated)
+    }
+}
+
+#[doc = "Integrated loudness of gated blocks, None when every block is under the absolute gate"]
+fn gated_loudness(blocks: &[f64], relative_gate: f64) -> Option<f64> {
+    let absolute = power(ABSOLUTE_GATE);
+    let gated: Vec<f64> = blocks.iter().copied().filter(|p| *p > absolute).collect();
+    if gated.is_empty() {
+        return None;
+    }
+    let relative = gated.iter().sum::<f64>() / gated.len() as f64 * 10f64.powf(relative_gate / 10.);
+    let (sum, count) = gated
+        .iter()
+        .filter(|p| **p > relative)
+        .fold((0., 0), |(sum, count), p| (sum + p, count + 1));
+    (count > 0).then(|| loudness(sum / count as f64))
+}
+
+#[doc = "Spread between the 10th and 95th percentiles of the gated short-term loudness"]
+fn loudness_range(short_terms: &[f64]) -> f64 {
+    let absolute = power(ABSOLUTE_GATE);
+    let gated: Vec<f64> = short_terms
+        .iter()
+        .copied()
+        .filter(|p| *p > absolute)
+        .collect();
+    if gated.is_empty() {
+        return 0.;
+    }
+    let relative = gated.iter().sum::<f64>() / gated.len() as f64 * 10f64.powf(RANGE_GATE / 10.);
+    let mut levels: Vec<f64> = gated
+        .into_iter()
+        .filter(|p| *p > relative)
+        .map(loudness)
+        .collect();
+    if levels.is_empty() {
+        return 0.;
+    }
+    levels.sort_by(f64::total_cmp);
+    let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+    percentile(0.95) - percentile(0.1)
+}
+
+#[doc = "Averages of `window` consecutive 100 ms segments every `step` segments"]
+fn windows(segments: &[f64], window: usize, step: usize) -> Vec<f64> {
+    if segments.len() < window {
+        return Vec::new();
+    }
+    (0..=segments.len() - window)
+        .step_by(step)
+        .map(|start| segments[start..start + window].iter().sum::<f64>() / window as f64)
+        .collect()
+}
+
+#[doc = "Decode `path` and measure its integrated loudness, loudness range and true peak"]
+pub fn measure_loudness(path: &PathBuf) -> Result<Measurement> {
+    let file = File::open(path).map_err(|e| Error::Io(path.clone(), e))?;
+    let decoder =
+        rodio::Decoder::new(BufReader::new(file)).map_err(|e| Error::Decode(path.clone(), e))?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate() as f64;
+    let weights = channel_weights(channels);
+    let mut stages = k_weighting(sample_rate, channels);
+    let mut true_peak = TruePeak::new(channels);
+    let segment_len = (sample_rate / 10.).round().max(1.) as usize * channels;
+    let mut segments = Vec::new();
+    let (mut sum, mut count) = (0., 0);
+    for (index, sample) in decoder.enumerate() {
+        let channel = index % channels;
+        let x = sample as f64;
+        true_peak.process(channel, x);
+        let y = stages
+            .iter_mut()
+            .fold(x, |x, stage| stage.process(channel, x));
+        sum += weights[channel] * y * y;
+        count += 1;
+        if count == segment_len {
+            segments.push(sum / (segment_len / channels) as f64);
+            (sum, count) = (0., 0);
+        }
+    }
+    let blocks = windows(&segments, 4, 1);
+    Ok(Measurement {
+        path: path.clone(),
+        integrated: gated_loudness(&blocks, RELATIVE_GATE),
+        range: loudness_range(&windows(&segments, 30, 10)),
+        true_peak: true_peak.peak,
+        blocks,
+    })
+}
+
+#[doc = "Integrated loudness and true peak of tracks played as one album"]
+pub fn measure_album_loudness(tracks: &[&Measurement]) -> (Option<f64>, f64) {
+    let blocks: Vec<f64> = tracks
+        .iter()
+        .flat_map(|track| track.blocks.iter().copied())
+        .collect();
+    let true_peak = tracks
+        .iter()
+        .map(|track| track.true_peak)
+        .fold(0., f64::max);
+    (gated_loudness(&blocks, RELATIVE_GATE), true_peak)
+}
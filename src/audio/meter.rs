@@ -0,0 +1,187 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+#[doc = "Samples processed between two exchanges with the shared meter state"]
+const CHECK_INTERVAL: usize = 256;
+
+#[doc = "Level above which the soft limiter starts bending the signal"]
+const LIMITER_THRESHOLD: f32 = 0.8;
+
+#[doc = "Levels of the left and right channels since the last read, linear"]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+    #[doc = "Latched once a sample reached full scale, until `Meter::reset_clip`"]
+    pub clipped: bool,
+    pub limiting: bool,
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    peak: [f32; 2],
+    squares: [f32; 2],
+    frames: usize,
+    clipped: bool,
+    limiting: bool,
+    #[doc = "Engage the limiter as soon as clipping is detected"]
+    auto_limit: bool,
+}
+
+#[doc = "Handle on the levels of the mixed output of the sinks, shared with every source it wrapped"]
+#[derive(Debug, Clone, Default)]
+pub struct Meter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl Meter {
+    #[doc = "Wrap the mixer of the sinks, measuring and optionally limiting their sum"]
+    pub fn wrap<I: Source>(&self, input: I) -> MeterSource<I> {
+        MeterSource {
+            input,
+            state: self.state.clone(),
+            peak: [0.; 2],
+            squares: [0.; 2],
+            frames: 0,
+            clipped: false,
+            limiting: false,
+            channel: 0,
+            until_check: 0,
+        }
+    }
+    #[doc = "Levels measured since the last read, the clip indicator stays latched"]
+    pub fn read(&self) -> Levels {
+        let Ok(mut state) = self.state.lock() else {
+            return Levels::default();
+        };
+        let frames = state.frames.max(1) as f32;
+        let levels = Levels {
+            peak: state.peak,
+            rms: state.squares.map(|squares| (squares / frames).sqrt()),
+            clipped: state.clipped,
+            limiting: state.limiting,
+        };
+        (state.peak, state.squares, state.frames) = ([0.; 2], [0.; 2], 0);
+        levels
+    }
+    pub fn reset_clip(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.clipped = false;
+        }
+    }
+    pub fn is_limiting(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.limiting)
+    }
+    pub fn set_limiter(&self, limiting: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.limiting = limiting;
+        }
+    }
+    pub fn set_auto_limit(&self, auto_limit: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.auto_limit = auto_limit;
+        }
+    }
+}
+
+#[doc = "Bend samples above `LIMITER_THRESHOLD` so that they never reach full scale"]
+fn soft_limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        return sample;
+    }
+    let headroom = 1. - LIMITER_THRESHOLD;
+    let limited =
+        LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+    limited.copysign(sample)
+}
+
+pub struct MeterSource<I> {
+    input: I,
+    state: Arc<Mutex<MeterState>>,
+    peak: [f32; 2],
+    squares: [f32; 2],
+    frames: usize,
+    clipped: bool,
+    limiting: bool,
+    channel: u16,
+    until_check: usize,
+}
+
+impl<I: Source> MeterSource<I> {
+    fn exchange(&mut self) {
+        let Ok(mut state) = self.state.try_lock() else {
+            return;
+        };
+        for side in 0..2 {
+            state.peak[side] = state.peak[side].max(self.peak[side]);
+            state.squares[side] += self.squares[side];
+        }
+        state.frames += self.frames;
+        if self.clipped {
+            state.clipped = true;
+            state.auto_limit.then(|| state.limiting = true);
+        }
+        self.limiting = state.limiting;
+        (self.peak, self.squares, self.frames, self.clipped) = ([0.; 2], [0.; 2], 0, false);
+    }
+}
+
+impl<I: Source> Iterator for MeterSource<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_check == 0 {
+            self.exchange();
+            self.until_check = CHECK_INTERVAL;
+        }
+        self.until_check -= 1;
+        let sample = self.input.next()?;
+        self.clipped |= sample.abs() >= 1.;
+        let sample = if self.limiting {
+            soft_limit(sample)
+        } else {
+            sample
+        };
+        let channels = self.input.channels().max(1);
+        // A mono stream feeds both sides, the channels after the second one are not metered
+        let sides = match (channels, self.channel) {
+            (1, _) => 0..2,
+            (_, 0) => 0..1,
+            (_, 1) => 1..2,
+            _ => 0..0,
+        };
+        for side in sides {
+            self.peak[side] = self.peak[side].max(sample.abs());
+            self.squares[side] += sample * sample;
+        }
+        self.channel += 1;
+        if self.channel >= channels {
+            self.channel = 0;
+            self.frames += 1;
+        }
+        Some(sample)
+    }
+}
+
+impl<I: Source> Source for MeterSource<I> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.input.try_seek(pos)
+    }
+}
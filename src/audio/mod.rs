@@ -1,77 +1,753 @@
+mod analyzer;
+mod biquad;
+mod cue;
+mod device;
+mod equalizer;
+mod fader;
+mod filter;
+mod loudness;
+mod meter;
+mod pitch;
+mod probe;
+mod queue;
+mod render;
+mod replay_gain;
+mod waveform;
+
 use std::{fs::File, io::BufReader, path::PathBuf, time::Duration};
 
+use lofty::{
+    file::{AudioFile, TaggedFileExt},
+    tag::Accessor,
+};
 use rodio::{
     Source,
-    cpal::{self, traits::HostTrait},
+    cpal::{self, traits::DeviceTrait},
 };
 
+use crate::error::{Error, Result};
+use analyzer::Analyzer;
+pub use cue::{CueTrack, Track, expand as expand_cue_sheets, is_cue_sheet, read_cue_sheet};
+pub use device::{DeviceSelector, OutputDevice, output_devices, print_devices};
+pub use equalizer::{EqPreset, EqSettings, Equalizer, MAX_GAIN};
+use fader::Fader;
+pub use fader::{Crossfade, FadeCurve};
+pub use filter::{FilterSettings, Filters};
+pub use loudness::{
+    Measurement, REFERENCE as REPLAY_GAIN_REFERENCE, measure_album_loudness, measure_loudness,
+};
+pub use meter::Levels;
+use meter::Meter;
+use pitch::PitchShift;
+pub use probe::verify_audio_file;
+pub use queue::{Queue, Repeat, Shuffle};
+pub use render::{RenderOptions, render_to_wav};
+pub use replay_gain::{ReplayGain, ReplayGainMode, ReplayGainSettings};
+pub use waveform::{PendingWaveform, Waveform};
+
 pub struct AudioSource {
-    title: String,
-    file: File,
+    tracks: Vec<Track>,
 }
 
 impl AudioSource {
-    pub fn from(path: PathBuf) -> Self {
-        Self {
-            title: path
-                .to_str()
-                .expect("[x] Could not convert title to string")
-                .to_string(),
-            file: File::open(path.clone()).expect(&format!(
-                "[x] Error while opening file {}",
-                path.to_string_lossy()
-            )),
-        }
+    pub fn from(track: impl Into<Track>) -> Self {
+        Self::from_tracks(vec![track.into()])
+    }
+    #[doc = "Source playing `tracks` in order, such as the entries of a playlist or of a CUE sheet"]
+    pub fn from_tracks(tracks: Vec<Track>) -> Self {
+        Self { tracks }
     }
+    #[doc = "Start playing on the device selected by `device`, or on the default one"]
     pub fn play(
         &mut self,
+        device: Option<DeviceSelector>,
         low_pass: Option<u32>,
         high_pass: Option<u32>,
         debug: bool,
-    ) -> Result<AudioPlayer, rodio::PlayError> {
-        let default_device = cpal::default_host()
-            .default_output_device()
-            .expect("[x] Rodio: Could not find output device");
-        let mut stream_handle = rodio::OutputStreamBuilder::from_device(default_device)
-            .expect("[x] Rodio: Could not user output device")
-            .open_stream_or_fallback()
-            .expect("Could not use audio device");
-        let sink = rodio::Sink::connect_new(stream_handle.mixer());
-        let decoder = rodio::Decoder::new(BufReader::new(
-            self.file.try_clone().expect("[x] Could not clone file"),
-        ))?;
-        if let Some(low_pass) = low_pass {
-            let src = decoder.low_pass(low_pass);
-            if high_pass.is_some() {
-                sink.append(src.high_pass(high_pass.unwrap()));
-                return Ok(AudioPlayer::from(stream_handle, sink));
-            }
-            sink.append(src);
-        } else {
-            if high_pass.is_some() {
-                sink.append(decoder.high_pass(high_pass.unwrap()));
-                return Ok(AudioPlayer::from(stream_handle, sink));
-            }
+    ) -> Result<AudioPlayer> {
+        let device = match device {
+            Some(selector) => selector.find()?.device,
+            None => device::default_device()?,
+        };
+        let device_name = device.name().unwrap_or_default();
+        let stream_handle = open_stream(device, debug)?;
+        let (analyzer, meter) = (Analyzer::default(), Meter::default());
+        let mixer = connect_output(&stream_handle, &analyzer, &meter);
+        let (sink, fader) = connect_sink(&mixer, None);
+        let mut player = AudioPlayer::from(stream_handle, mixer, sink, fader, analyzer, meter);
+        player.set_filters(FilterSettings::from(low_pass, high_pass));
+        player.device_name = device_name;
+        player.queue = Queue::from(self.tracks.clone());
+        player.load_current()?;
+        Ok(player)
+    }
+}
 
-            sink.append(decoder);
-        }
-        stream_handle.log_on_drop(debug);
-        Ok(AudioPlayer::from(stream_handle, sink))
+fn open_stream(device: cpal::Device, debug: bool) -> Result<rodio::OutputStream> {
+    let mut stream_handle = rodio::OutputStreamBuilder::from_device(device)
+        .map_err(Error::Device)?
+        .open_stream_or_fallback()
+        .map_err(Error::Device)?;
+    stream_handle.log_on_drop(debug);
+    Ok(stream_handle)
+}
+
+#[doc = "Output of the mixer of the sinks, silent rather than finished while no sink plays so that the stream keeps it"]
+struct Endless(rodio::mixer::MixerSource);
+
+impl Iterator for Endless {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.0.next().unwrap_or(0.))
+    }
+}
+
+impl Source for Endless {
+    fn current_span_len(&self) -> Option<usize> {
+        None
     }
-    pub fn get_title(&self) -> &str {
-        &self.title
+    fn channels(&self) -> rodio::ChannelCount {
+        self.0.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.0.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[doc = "Create the mixer the sinks play into, its sum goes through the meter and the analyzer once before reaching the stream so that overlapping tracks are measured and limited together"]
+fn connect_output(
+    stream: &rodio::OutputStream,
+    analyzer: &Analyzer,
+    meter: &Meter,
+) -> rodio::mixer::Mixer {
+    let config = stream.config();
+    let (mixer, output) = rodio::mixer::mixer(config.channel_count(), config.sample_rate());
+    stream
+        .mixer()
+        .add(analyzer.tap(meter.wrap(Endless(output))));
+    mixer
+}
+
+#[doc = "Create a sink whose whole output goes through a fader before reaching the mixer"]
+fn connect_sink(mixer: &rodio::mixer::Mixer, fade_in: Option<Crossfade>) -> (rodio::Sink, Fader) {
+    let (sink, output) = rodio::Sink::new();
+    let fader = Fader::default();
+    mixer.add(fader.wrap(output, fade_in));
+    (sink, fader)
+}
+
+fn read_duration(path: &PathBuf) -> Option<Duration> {
+    lofty::read_from_path(path)
+        .ok()
+        .map(|file| file.properties().duration())
+        .or_else(|| decoded_duration(path))
+}
+
+#[doc = "Duration reported by the decoder, for containers such as Matroska or CAF whose properties lofty cannot read"]
+pub fn decoded_duration(path: &PathBuf) -> Option<Duration> {
+    let file = File::open(path).ok()?;
+    rodio::Decoder::new(BufReader::new(file))
+        .ok()?
+        .total_duration()
+}
+
+#[doc = "Duration of a queue entry, from its start to the end of its CUE track or of its file"]
+fn track_duration(track: &Track) -> Option<Duration> {
+    let end = track.end().or_else(|| read_duration(&track.path))?;
+    Some(end.saturating_sub(track.start()))
+}
+
+fn read_artist(path: &PathBuf) -> Option<String> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let tag = tagged_file.primary_tag().or(tagged_file.first_tag())?;
+    tag.artist()
+        .map(|artist| artist.to_string())
+        .filter(|artist| !artist.is_empty())
+}
+
+fn read_album(path: &PathBuf) -> Option<String> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let tag = tagged_file.primary_tag().or(tagged_file.first_tag())?;
+    tag.album()
+        .map(|album| album.to_string())
+        .filter(|album| !album.is_empty())
+}
+
+#[doc = "Decode a file, amplify it by `gain`, shift its pitch and apply the filters and the equalizer on it"]
+fn open_source(
+    path: &PathBuf,
+    gain: f32,
+    pitch: &PitchShift,
+    filters: &Filters,
+    equalizer: &Equalizer,
+) -> Result<Box<dyn Source + Send>> {
+    let file = File::open(path).map_err(|e| Error::Io(path.clone(), e))?;
+    let decoder =
+        rodio::Decoder::new(BufReader::new(file)).map_err(|e| Error::Decode(path.clone(), e))?;
+    Ok(Box::new(
+        equalizer.wrap(filters.wrap(pitch.wrap(decoder.amplify(gain)))),
+    ))
+}
+
+#[doc = "Frequency ratio of a shift in semitones"]
+fn semitones_ratio(semitones: i32) -> f32 {
+    2f32.powf(semitones as f32 / 12.)
+}
+
+#[doc = "Ratio the pitch shifter applies for a playback speed, undoing the pitch change of the speed when `preserve` is set"]
+fn pitch_ratio(speed: f32, semitones: i32, preserve: bool) -> f32 {
+    let semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+    let ratio = semitones_ratio(semitones) / if preserve { speed } else { 1. };
+    // Speeds built from steps of 0.1 are not exact, stay bypassed around 1
+    if (ratio - 1.).abs() < 1e-4 { 1. } else { ratio }
+}
+
+#[doc = "Range of the pitch shift in semitones"]
+pub const MAX_SEMITONES: i32 = 12;
+
+#[doc = "Range of the playback speed"]
+pub const SPEED_RANGE: (f32, f32) = (0.1, 2.0);
+
+#[doc = "Range of the playback volume"]
+pub const VOLUME_RANGE: (f32, f32) = (0.0, 2.0);
+
+#[doc = "Segment of a track played over and over, only A is set until B is marked"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbLoop {
+    #[doc = "Queue entry the loop was set on, a CUE track and not only its file"]
+    track: Track,
+    pub a: Duration,
+    pub b: Option<Duration>,
+}
+
+#[doc = "State of the track following the current one in the queue"]
+#[derive(Debug, Default)]
+enum Preload {
+    #[default]
+    None,
+    Appended(Track),
+    Crossfade(Track),
+    #[doc = "Opened once the current track ends, CUE tracks share their file or start inside it"]
+    Deferred(Track),
+    Failed(Track),
+}
+
+impl Preload {
+    fn track(&self) -> Option<&Track> {
+        match self {
+            Preload::None => None,
+            Preload::Appended(track)
+            | Preload::Crossfade(track)
+            | Preload::Deferred(track)
+            | Preload::Failed(track) => Some(track),
+        }
     }
 }
 
 pub struct AudioPlayer {
-    #[allow(dead_code)]
     handle: rodio::OutputStream,
+    #[doc = "Mixer of the sinks, measured as a whole"]
+    mixer: rodio::mixer::Mixer,
+    device_name: String,
     sink: rodio::Sink,
+    fader: Fader,
+    #[doc = "Sink of the previous track while it fades out under the current one"]
+    fading: Option<(rodio::Sink, Fader)>,
+    queue: Queue,
+    preload: Preload,
+    track_duration: Option<Duration>,
+    crossfade: Option<Crossfade>,
+    replay_gain: ReplayGainSettings,
+    #[doc = "Linear ReplayGain factor applied to the current track"]
+    track_gain: f32,
+    filters: Filters,
+    equalizer: Equalizer,
+    pitch: PitchShift,
+    #[doc = "Pitch shift in semitones on top of the speed"]
+    semitones: i32,
+    #[doc = "Keep the pitch when the speed changes instead of letting it follow like a tape"]
+    preserve_pitch: bool,
+    analyzer: Analyzer,
+    meter: Meter,
+    ab_loop: Option<AbLoop>,
 }
 
 impl AudioPlayer {
-    fn from(h: rodio::OutputStream, s: rodio::Sink) -> Self {
-        Self { handle: h, sink: s }
+    fn from(
+        h: rodio::OutputStream,
+        x: rodio::mixer::Mixer,
+        s: rodio::Sink,
+        f: Fader,
+        a: Analyzer,
+        m: Meter,
+    ) -> Self {
+        Self {
+            handle: h,
+            mixer: x,
+            device_name: String::new(),
+            sink: s,
+            fader: f,
+            fading: None,
+            queue: Queue::default(),
+            preload: Preload::None,
+            track_duration: None,
+            crossfade: None,
+            replay_gain: ReplayGainSettings::default(),
+            track_gain: 1.,
+            filters: Filters::default(),
+            equalizer: Equalizer::default(),
+            pitch: PitchShift::default(),
+            semitones: 0,
+            preserve_pitch: false,
+            analyzer: a,
+            meter: m,
+            ab_loop: None,
+        }
+    }
+    #[doc = "Enable crossfading between tracks that do not belong to the same album"]
+    pub fn set_crossfade(&mut self, crossfade: Option<Crossfade>) -> &mut Self {
+        self.crossfade = crossfade;
+        self.reset_preload();
+        self
+    }
+    #[doc = "Replace the sink content with the track under the queue cursor"]
+    fn load_current(&mut self) -> Result<()> {
+        self.preload = Preload::None;
+        self.fading = None;
+        let (Some(track), Some(index)) =
+            (self.queue.current().cloned(), self.queue.current_index())
+        else {
+            self.sink.clear();
+            return Ok(());
+        };
+        let gain = self.gain(index);
+        let source = open_source(
+            &track.path,
+            gain,
+            &self.pitch,
+            &self.filters,
+            &self.equalizer,
+        )?;
+        self.track_gain = gain;
+        self.track_duration = track_duration(&track);
+        self.sink.clear();
+        self.sink.append(source);
+        if track.start() > Duration::ZERO {
+            let _ = self.sink.try_seek(track.start());
+        }
+        self.sink.play();
+        Ok(())
+    }
+    #[doc = "Decode the next track of the queue ahead of time and append it to the sink so it starts without gap"]
+    fn preload_next(&mut self) {
+        let Some(next) = self.queue.peek_next().cloned() else {
+            return;
+        };
+        if self.queue.current().and_then(Track::end).is_some() || next.start() > Duration::ZERO {
+            self.preload = Preload::Deferred(next);
+            return;
+        }
+        if self.crossfade.is_some() {
+            let album = self
+                .queue
+                .current()
+                .and_then(|current| read_album(&current.path));
+            if album.is_none() || album != read_album(&next.path) {
+                self.preload = Preload::Crossfade(next);
+                return;
+            }
+        }
+        let gain = self
+            .queue
+            .current_index()
+            .map_or(1., |index| self.gain(index + 1));
+        self.preload = match open_source(
+            &next.path,
+            gain,
+            &self.pitch,
+            &self.filters,
+            &self.equalizer,
+        ) {
+            Ok(source) => {
+                self.sink.append(source);
+                Preload::Appended(next)
+            }
+            Err(_) => Preload::Failed(next),
+        };
+    }
+    #[doc = "Start the next track on a new sink fading in while the current one fades out"]
+    fn start_crossfade(&mut self, next: Track, crossfade: Crossfade) -> bool {
+        let gain = self
+            .queue
+            .current_index()
+            .map_or(1., |index| self.gain(index + 1));
+        let source = match open_source(
+            &next.path,
+            gain,
+            &self.pitch,
+            &self.filters,
+            &self.equalizer,
+        ) {
+            Ok(source) => source,
+            Err(_) => {
+                self.preload = Preload::Failed(next);
+                return false;
+            }
+        };
+        let (sink, fader) = connect_sink(&self.mixer, Some(crossfade));
+        sink.set_volume(self.sink.volume());
+        sink.set_speed(self.sink.speed());
+        sink.append(source);
+        self.fader.fade_out(crossfade);
+        let sink = std::mem::replace(&mut self.sink, sink);
+        let fader = std::mem::replace(&mut self.fader, fader);
+        self.fading = Some((sink, fader));
+        self.preload = Preload::None;
+        self.track_gain = gain;
+        self.track_duration = track_duration(&next);
+        self.queue.advance();
+        true
+    }
+    #[doc = "ReplayGain factor of the track at `index` in the queue"]
+    fn gain(&self, index: usize) -> f32 {
+        let tracks = self.queue.tracks();
+        let Some(path) = tracks.get(index).map(|track| &track.path) else {
+            return 1.;
+        };
+        if self.replay_gain.mode == ReplayGainMode::Off {
+            return 1.;
+        }
+        let album = self.replay_gain.mode == ReplayGainMode::Auto && {
+            let album = read_album(path);
+            album.is_some()
+                && [index.checked_sub(1), Some(index + 1)]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|neighbour| tracks.get(neighbour))
+                    .any(|neighbour| read_album(&neighbour.path) == album)
+        };
+        ReplayGain::read(path).factor(self.replay_gain, album)
+    }
+    #[doc = "Reopen the current track at its position, picking up the settings applied when a track is opened"]
+    fn reload(&mut self) {
+        let position = self.sink.get_pos();
+        let paused = self.sink.is_paused();
+        if self.load_current().is_ok() {
+            let _ = self.sink.try_seek(position);
+            paused.then(|| self.sink.pause());
+        }
+    }
+    #[doc = "Drop the preloaded track from the sink by reloading the current one at its position"]
+    fn reset_preload(&mut self) {
+        if !matches!(self.preload, Preload::Appended(_)) {
+            self.preload = Preload::None;
+            return;
+        }
+        self.reload();
+    }
+    #[doc = "Follow the sink into the preloaded track or advance the queue when the current track has ended, returns true when a new track started"]
+    pub fn update(&mut self) -> bool {
+        if self
+            .ab_loop
+            .as_ref()
+            .is_some_and(|ab_loop| Some(&ab_loop.track) != self.queue.current())
+        {
+            self.ab_loop = None;
+        }
+        if let Some(AbLoop { a, b: Some(b), .. }) = self.ab_loop
+            && self.sink.get_pos() >= b
+        {
+            let _ = self.sink.try_seek(a);
+            return false;
+        }
+        if let Some(end) = self.queue.current().and_then(Track::end)
+            && self.sink.get_pos() >= end
+        {
+            return self.next_cue_track(end);
+        }
+        if self
+            .fading
+            .as_ref()
+            .is_some_and(|(sink, fader)| sink.empty() || fader.is_done())
+        {
+            self.fading = None;
+        }
+        if let (Preload::Crossfade(next), Some(crossfade), None) =
+            (&self.preload, self.crossfade, &self.fading)
+            && let Some(total) = self.track_duration
+        {
+            // Short tracks overlap for at most half their length
+            let crossfade = Crossfade {
+                duration: crossfade.duration.min(total / 2),
+                ..crossfade
+            };
+            if total.saturating_sub(self.get_current_duration()) <= crossfade.duration {
+                let next = next.clone();
+                return self.start_crossfade(next, crossfade);
+            }
+        }
+        if matches!(self.preload, Preload::Appended(_)) && self.sink.len() <= 1 {
+            self.preload = Preload::None;
+            self.queue.advance();
+            self.track_gain = self
+                .queue
+                .current_index()
+                .map_or(1., |index| self.gain(index));
+            self.track_duration = self.queue.current().and_then(track_duration);
+            return true;
+        }
+        if self.sink.empty() {
+            // Bounded so that repeating a queue of unreadable tracks does not loop forever
+            for _ in 0..self.queue.tracks().len() {
+                if self.queue.advance().is_none() {
+                    break;
+                }
+                if self.load_current().is_ok() {
+                    return true;
+                }
+            }
+            return false;
+        }
+        if self.preload.track() != self.queue.peek_next() {
+            self.reset_preload();
+            self.preload_next();
+        }
+        false
+    }
+    #[doc = "Move to the next entry of the queue once the CUE track playing reached `end`, the sink keeps playing when it is the following track of the same file"]
+    fn next_cue_track(&mut self, end: Duration) -> bool {
+        let path = self.current_track().cloned();
+        let Some(next) = self.queue.advance().cloned() else {
+            // The queue ends with this track, the rest of the file is not played
+            self.sink.clear();
+            return false;
+        };
+        self.preload = Preload::None;
+        self.track_duration = track_duration(&next);
+        let continues = Some(&next.path) == path.as_ref()
+            && next.start().abs_diff(end) < Duration::from_millis(50);
+        if continues {
+            return true;
+        }
+        if Some(&next.path) == path.as_ref() {
+            let _ = self.sink.try_seek(next.start());
+            return true;
+        }
+        self.load_current().is_ok()
+    }
+    #[doc = "Change how ReplayGain is applied, the current track is reopened at its position to use it"]
+    pub fn set_replay_gain(&mut self, settings: ReplayGainSettings) -> &mut Self {
+        if self.replay_gain != settings {
+            self.replay_gain = settings;
+            self.preload = Preload::None;
+            (!self.sink.empty()).then(|| self.reload());
+        }
+        self
+    }
+    pub fn replay_gain(&self) -> ReplayGainSettings {
+        self.replay_gain
+    }
+    #[doc = "ReplayGain applied to the current track in dB"]
+    pub fn track_gain_db(&self) -> f32 {
+        20. * self.track_gain.log10()
+    }
+    #[doc = "Change the low-pass and high-pass filters of the playing track and the ones after it"]
+    pub fn set_filters(&mut self, settings: FilterSettings) -> &mut Self {
+        self.filters.set(settings);
+        self
+    }
+    pub fn filter_settings(&self) -> FilterSettings {
+        self.filters.settings()
+    }
+    #[doc = "Apply new equalizer settings to the playing track and the ones after it"]
+    pub fn set_equalizer(&mut self, settings: EqSettings) -> &mut Self {
+        self.equalizer.set(settings);
+        self
+    }
+    pub fn equalizer_settings(&self) -> EqSettings {
+        self.equalizer.settings()
+    }
+    fn update_pitch(&self) {
+        self.pitch.set(pitch_ratio(
+            self.sink.speed(),
+            self.semitones,
+            self.preserve_pitch,
+        ));
+    }
+    #[doc = "Keep the pitch when the speed changes, or let it follow the speed"]
+    pub fn set_preserve_pitch(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_pitch = preserve;
+        self.update_pitch();
+        self
+    }
+    pub fn preserve_pitch(&self) -> bool {
+        self.preserve_pitch
+    }
+    #[doc = "Shift the pitch by `semitones` without changing the speed, clamped to `MAX_SEMITONES`"]
+    pub fn set_semitones(&mut self, semitones: i32) -> &mut Self {
+        self.semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        self.update_pitch();
+        self
+    }
+    pub fn semitones(&self) -> i32 {
+        self.semitones
+    }
+    pub fn speed(&self) -> f32 {
+        self.sink.speed()
+    }
+    #[doc = "Play at `speed`, clamped to `SPEED_RANGE`"]
+    pub fn set_speed(&mut self, speed: f32) -> &mut Self {
+        self.sink
+            .set_speed(speed.clamp(SPEED_RANGE.0, SPEED_RANGE.1));
+        if let Some((sink, _)) = &self.fading {
+            sink.set_speed(self.sink.speed());
+        }
+        self.update_pitch();
+        self
+    }
+    pub fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+    #[doc = "Play at `volume`, clamped to `VOLUME_RANGE`"]
+    pub fn set_volume(&mut self, volume: f32) -> &mut Self {
+        self.sink
+            .set_volume(volume.clamp(VOLUME_RANGE.0, VOLUME_RANGE.1));
+        self
+    }
+    #[doc = "Level from 0 to 1 of `bands` log spaced frequency bands of what is playing"]
+    pub fn spectrum(&self, bands: usize) -> Vec<f32> {
+        self.analyzer.spectrum(bands)
+    }
+    #[doc = "Peak and RMS levels of the output since the last call, after volume and filters"]
+    pub fn levels(&self) -> Levels {
+        self.meter.read()
+    }
+    pub fn reset_clip(&self) {
+        self.meter.reset_clip();
+    }
+    pub fn is_limiting(&self) -> bool {
+        self.meter.is_limiting()
+    }
+    pub fn set_limiter(&self, limiting: bool) {
+        self.meter.set_limiter(limiting);
+    }
+    #[doc = "Engage the soft limiter automatically once the output clips"]
+    pub fn set_auto_limiter(&mut self, auto_limit: bool) -> &mut Self {
+        self.meter.set_auto_limit(auto_limit);
+        self
+    }
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+    #[doc = "Move playback to another output device, resuming the current track where it was"]
+    pub fn switch_device(&mut self, device: OutputDevice, debug: bool) -> Result<()> {
+        let position = self.sink.get_pos();
+        let paused = self.sink.is_paused();
+        let (volume, speed) = (self.sink.volume(), self.sink.speed());
+        let stream_handle = open_stream(device.device, debug)?;
+        let mixer = connect_output(&stream_handle, &self.analyzer, &self.meter);
+        let (sink, fader) = connect_sink(&mixer, None);
+        self.sink.stop();
+        self.sink = sink;
+        self.fader = fader;
+        self.fading = None;
+        self.handle = stream_handle;
+        self.mixer = mixer;
+        self.device_name = device.name;
+        self.sink.set_volume(volume);
+        self.sink.set_speed(speed);
+        self.load_current()?;
+        let _ = self.sink.try_seek(position);
+        paused.then(|| self.sink.pause());
+        Ok(())
+    }
+    pub fn get_queue(&self) -> &Queue {
+        &self.queue
+    }
+    #[doc = "File of the current queue entry"]
+    pub fn current_track(&self) -> Option<&PathBuf> {
+        self.queue.current().map(|track| &track.path)
+    }
+    #[doc = "CUE track played when the current queue entry is one"]
+    pub fn current_cue(&self) -> Option<&CueTrack> {
+        self.queue.current().and_then(|track| track.cue.as_ref())
+    }
+    #[doc = "Replace the queue, the current track keeps playing if it is the one under the new cursor"]
+    pub fn set_queue(&mut self, tracks: Vec<Track>, start: usize) -> Result<()> {
+        let playing = self.queue.current().cloned();
+        let (repeat, shuffle) = (self.queue.repeat(), self.queue.shuffle());
+        self.queue = Queue::starting_at(tracks, start);
+        self.queue.set_repeat(repeat);
+        self.queue.set_shuffle(shuffle);
+        if self.queue.current() != playing.as_ref() {
+            self.load_current()?;
+        }
+        Ok(())
+    }
+    pub fn repeat(&self) -> Repeat {
+        self.queue.repeat()
+    }
+    #[doc = "Change what plays once a track ends, the preloaded track is dropped since it may no longer be the next one"]
+    pub fn set_repeat(&mut self, repeat: Repeat) -> &mut Self {
+        if repeat != self.queue.repeat() {
+            self.queue.set_repeat(repeat);
+            self.reset_preload();
+        }
+        self
+    }
+    pub fn shuffle(&self) -> Shuffle {
+        self.queue.shuffle()
+    }
+    #[doc = "Shuffle the tracks after the current one, or restore the order of the queue when `shuffle` is Off"]
+    pub fn set_shuffle(&mut self, shuffle: Shuffle) -> &mut Self {
+        if shuffle != self.queue.shuffle() {
+            self.queue.set_shuffle(shuffle);
+            self.reset_preload();
+        }
+        self
+    }
+    #[doc = "Add a track to the end of the queue, it starts playing when the playing track was removed from the end"]
+    pub fn enqueue(&mut self, track: Track) -> Result<()> {
+        let ended = self.queue.current().is_none() && !self.queue.tracks().is_empty();
+        self.queue.push(track);
+        match ended {
+            true => self.load_current(),
+            false => Ok(()),
+        }
+    }
+    pub fn next_track(&mut self) -> Result<()> {
+        if self.queue.next().is_some() {
+            self.load_current()?;
+        }
+        Ok(())
+    }
+    #[doc = "Go back to the previous track, or to the start of the current one when it played for more than 3 seconds"]
+    pub fn previous_track(&mut self) -> Result<()> {
+        if self.get_current_duration() > Duration::from_secs(3) || self.queue.previous().is_none() {
+            self.rewind();
+            return Ok(());
+        }
+        self.load_current()
+    }
+    pub fn jump_to(&mut self, index: usize) -> Result<()> {
+        if self.queue.jump_to(index).is_some() {
+            self.load_current()?;
+        }
+        Ok(())
+    }
+    pub fn remove_track(&mut self, index: usize) -> Result<()> {
+        let playing = self.queue.current_index() == Some(index);
+        if self.queue.remove(index).is_some() && playing {
+            self.load_current()?;
+        }
+        Ok(())
+    }
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        self.queue.move_track(from, to)
     }
     pub fn is_empty(&self) -> bool {
         self.sink.empty()
@@ -79,37 +755,131 @@ impl AudioPlayer {
     pub fn is_paused(&self) -> bool {
         self.sink.is_paused()
     }
+    #[doc = "Position in the current track, from the start of its CUE track when it is one"]
     pub fn get_current_duration(&self) -> Duration {
+        let start = self.queue.current().map_or(Duration::ZERO, Track::start);
+        self.sink.get_pos().saturating_sub(start)
+    }
+    #[doc = "Duration of the current track, of its CUE track when it is one"]
+    pub fn get_total_duration(&self) -> Option<Duration> {
+        self.track_duration
+    }
+    #[doc = "Position in the file of the current track, whatever CUE track is playing"]
+    pub fn file_position(&self) -> Duration {
         self.sink.get_pos()
     }
+    #[doc = "Seek to a position in the file of the current track, moving to the CUE track it falls in"]
+    pub fn seek_file_position(&mut self, position: Duration) {
+        let Some(path) = self.current_track().cloned() else {
+            return;
+        };
+        let index = self.queue.tracks().iter().position(|track| {
+            track.path == path
+                && track.start() <= position
+                && track.end().is_none_or(|end| position < end)
+        });
+        match index {
+            Some(index) if Some(index) != self.queue.current_index() => {
+                if self.jump_to(index).is_ok() {
+                    let _ = self.sink.try_seek(position);
+                }
+            }
+            _ => {
+                self.fading = None;
+                let _ = self.sink.try_seek(position);
+            }
+        }
+    }
     pub fn pause(&mut self) {
         self.sink.pause();
+        if let Some((sink, _)) = &self.fading {
+            sink.pause();
+        }
     }
     pub fn play(&mut self) {
         self.sink.play();
+        if let Some((sink, _)) = &self.fading {
+            sink.play();
+        }
+    }
+    #[doc = "Seek in the current track, the position saturates at the end of the track when its duration is known"]
+    pub fn seek_to(&mut self, position: Duration) {
+        let position = self
+            .track_duration
+            .map_or(position, |total| position.min(total));
+        let start = self.queue.current().map_or(Duration::ZERO, Track::start);
+        self.fading = None;
+        let _ = self.sink.try_seek(start + position);
     }
-    pub fn fast_forward(&mut self) {
-        let _ = self
-            .sink
-            .try_seek(self.sink.get_pos() + Duration::from_secs(5));
+    #[doc = "Seek forward, or backward with a negative amount of seconds"]
+    pub fn seek_relative(&mut self, seconds: i64) {
+        let current = self.get_current_duration();
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        self.seek_to(if seconds < 0 {
+            current.saturating_sub(offset)
+        } else {
+            current + offset
+        });
+    }
+    #[doc = "Seek to a fraction of the current track, from 0.0 (start) to 1.0 (end)"]
+    pub fn seek_fraction(&mut self, fraction: f64) {
+        if let Some(total) = self.track_duration {
+            self.seek_to(total.mul_f64(fraction.clamp(0., 1.)));
+        }
+    }
+    #[doc = "Mark the start of the loop at the current position, B is kept when it is still after it"]
+    pub fn set_loop_a(&mut self) {
+        let Some(track) = self.queue.current().cloned() else {
+            return;
+        };
+        let a = self.sink.get_pos();
+        let b = self
+            .ab_loop
+            .as_ref()
+            .and_then(|ab_loop| ab_loop.b)
+            .filter(|b| *b > a);
+        self.ab_loop = Some(AbLoop { track, a, b });
+    }
+    #[doc = "Mark the end of the loop at the current position and start looping, A defaults to the start of the track"]
+    pub fn set_loop_b(&mut self) {
+        let Some(track) = self.queue.current().cloned() else {
+            return;
+        };
+        let position = self.sink.get_pos();
+        let a = self
+            .ab_loop
+            .as_ref()
+            .map_or(track.start(), |ab_loop| ab_loop.a);
+        let (a, b) = (a.min(position), a.max(position));
+        self.ab_loop = (a != b).then_some(AbLoop {
+            track,
+            a,
+            b: Some(b),
+        });
+    }
+    pub fn clear_loop(&mut self) {
+        self.ab_loop = None;
+    }
+    pub fn ab_loop(&self) -> Option<&AbLoop> {
+        self.ab_loop.as_ref()
     }
     pub fn rewind(&mut self) {
-        let _ = self.sink.try_seek(Duration::from_secs(0));
+        self.seek_to(Duration::ZERO);
+    }
+    #[doc = "Change the speed by `step`, rounded to a tenth so that the steps add up exactly"]
+    fn step_speed(&mut self, step: f32) {
+        self.set_speed(((self.sink.speed() + step) * 10.).round() / 10.);
     }
     pub fn faster_playback(&mut self) {
-        self.sink
-            .set_speed((self.sink.speed() + 0.1).clamp(0.1, 2.0));
+        self.step_speed(0.1);
     }
     pub fn slower_playback(&mut self) {
-        self.sink
-            .set_speed((self.sink.speed() - 0.1).clamp(0.1, 2.0));
+        self.step_speed(-0.1);
     }
     pub fn higher_volume(&mut self) {
-        self.sink
-            .set_volume((self.sink.volume() + 0.1).clamp(0.0, 2.0));
+        self.set_volume(self.sink.volume() + 0.1);
     }
     pub fn lower_volume(&mut self) {
-        self.sink
-            .set_volume((self.sink.volume() - 0.1).clamp(0.0, 2.0));
+        self.set_volume(self.sink.volume() - 0.1);
     }
 }
@@ -0,0 +1,368 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+use super::biquad::{self, Coefficients, Filter};
+
+#[doc = "Samples processed between two reads of the shared ratio"]
+const CHECK_INTERVAL: usize = 256;
+
+#[doc = "Length of a WSOLA frame in seconds"]
+const FRAME: f32 = 0.04;
+
+#[doc = "How far around its nominal position a frame is searched for the best overlap, in seconds"]
+const TOLERANCE: f32 = 0.006;
+
+#[doc = "Step between the samples compared while searching, trading precision for speed"]
+const SEARCH_STEP: usize = 4;
+
+#[doc = "Frames of consumed samples a buffer keeps before moving the rest to its front"]
+const COMPACT_FRAMES: usize = 8;
+
+#[doc = "Cutoff of the anti-alias filter as a fraction of the Nyquist frequency left after resampling"]
+const ANTI_ALIAS: f32 = 0.9;
+
+#[doc = "Q of the two biquads of a 4th order Butterworth low-pass"]
+const BUTTERWORTH_Q: [f32; 2] = [0.541_196_1, 1.306_563];
+
+#[doc = "Handle on the pitch ratio shared by every source it wrapped, changes apply while playing"]
+#[derive(Debug, Clone)]
+pub struct PitchShift {
+    ratio: Arc<Mutex<f32>>,
+}
+
+impl Default for PitchShift {
+    fn default() -> Self {
+        Self {
+            ratio: Arc::new(Mutex::new(1.)),
+        }
+    }
+}
+
+impl PitchShift {
+    pub fn wrap<I: Source>(&self, input: I) -> PitchSource<I> {
+        PitchSource {
+            input,
+            shared: self.ratio.clone(),
+            ratio: 1.,
+            shifter: None,
+            channel: 0,
+            until_check: 0,
+        }
+    }
+    #[doc = "Multiply the frequencies by `ratio` without changing the duration, ratios that are not finite and positive are ignored"]
+    pub fn set(&self, ratio: f32) {
+        if !(ratio.is_finite() && ratio > 0.) {
+            return;
+        }
+        if let Ok(mut shared) = self.ratio.lock() {
+            *shared = ratio;
+        }
+    }
+}
+
+#[doc = "Per channel buffers read from an offset, the consumed samples are dropped in one go once enough piled up"]
+struct Buffer {
+    channels: Vec<Vec<f32>>,
+    offset: usize,
+    #[doc = "Consumed samples per channel kept before compacting"]
+    compact_at: usize,
+}
+
+impl Buffer {
+    fn new(channels: usize, compact_at: usize) -> Self {
+        Self {
+            channels: vec![Vec::new(); channels],
+            offset: 0,
+            compact_at,
+        }
+    }
+    fn len(&self) -> usize {
+        self.channels[0].len() - self.offset
+    }
+    fn get(&self, channel: usize, index: usize) -> f32 {
+        self.channels[channel]
+            .get(self.offset + index)
+            .copied()
+            .unwrap_or_default()
+    }
+    fn push(&mut self, channel: usize, sample: f32) {
+        self.channels[channel].push(sample);
+    }
+    fn consume(&mut self, count: usize) {
+        self.offset = (self.offset + count).min(self.channels[0].len());
+        if self.offset >= self.compact_at {
+            let offset = self.offset;
+            self.channels
+                .iter_mut()
+                .for_each(|channel| drop(channel.drain(..offset)));
+            self.offset = 0;
+        }
+    }
+}
+
+#[doc = "Low-pass filtering the stretched frames before they are resampled by `ratio`, empty when the ratio does not decimate"]
+fn anti_alias(sample_rate: u32, ratio: f32) -> Vec<Coefficients> {
+    if ratio <= 1. {
+        return Vec::new();
+    }
+    let cutoff = sample_rate as f32 / 2. * ANTI_ALIAS / ratio;
+    BUTTERWORTH_Q
+        .iter()
+        .map(|q| biquad::low_pass(cutoff, *q, sample_rate as f32))
+        .collect()
+}
+
+#[doc = "WSOLA time stretch by `1 / ratio` followed by a resampling by `ratio`, the duration is kept and the pitch moves"]
+struct Shifter {
+    channels: usize,
+    sample_rate: u32,
+    ratio: f64,
+    frame: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    #[doc = "Input not consumed yet"]
+    input: Buffer,
+    input_done: bool,
+    #[doc = "Nominal start of the next analysis frame in `input`"]
+    position: f64,
+    #[doc = "Where the frame chosen last would naturally continue in `input`"]
+    continuation: Option<usize>,
+    #[doc = "Second half of the last windowed frame, added to the first half of the next one"]
+    tail: Vec<Vec<f32>>,
+    #[doc = "Stretched frames waiting to be resampled"]
+    stretched: Buffer,
+    anti_alias: Vec<Filter>,
+    #[doc = "Read position of the resampler in `stretched`"]
+    read: f64,
+    output: VecDeque<f32>,
+}
+
+impl Shifter {
+    fn new(channels: usize, sample_rate: u32, ratio: f32) -> Self {
+        let frame = ((sample_rate as f32 * FRAME) as usize / 2 * 2).max(4);
+        let hop = frame / 2;
+        Self {
+            channels,
+            sample_rate,
+            ratio: ratio as f64,
+            frame,
+            hop,
+            tolerance: (sample_rate as f32 * TOLERANCE) as usize,
+            window: (0..frame)
+                .map(|n| 0.5 - 0.5 * (2. * PI * n as f32 / frame as f32).cos())
+                .collect(),
+            input: Buffer::new(channels, frame * COMPACT_FRAMES),
+            input_done: false,
+            position: 0.,
+            continuation: None,
+            tail: vec![vec![0.; hop]; channels],
+            stretched: Buffer::new(channels, frame * COMPACT_FRAMES),
+            anti_alias: anti_alias(sample_rate, ratio)
+                .into_iter()
+                .map(|coefficients| Filter::new(coefficients, channels))
+                .collect(),
+            read: 0.,
+            output: VecDeque::new(),
+        }
+    }
+    #[doc = "Change the ratio from the next frame on, the buffered input and the filter history are kept"]
+    fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio as f64;
+        let coefficients = anti_alias(self.sample_rate, ratio);
+        if coefficients.len() == self.anti_alias.len() {
+            self.anti_alias
+                .iter_mut()
+                .zip(coefficients)
+                .for_each(|(filter, coefficients)| filter.coefficients = coefficients);
+        } else {
+            self.anti_alias = coefficients
+                .into_iter()
+                .map(|coefficients| Filter::new(coefficients, self.channels))
+                .collect();
+        }
+    }
+    fn mono(&self, index: usize) -> f32 {
+        (0..self.channels)
+            .map(|channel| self.input.get(channel, index))
+            .sum()
+    }
+    #[doc = "Offset around the nominal position whose start overlaps best with the continuation of the previous frame"]
+    fn best_offset(&self, nominal: usize) -> usize {
+        let Some(continuation) = self.continuation else {
+            return nominal;
+        };
+        let natural: Vec<f32> = (0..self.hop)
+            .step_by(SEARCH_STEP)
+            .map(|index| self.mono(continuation + index))
+            .collect();
+        (nominal.saturating_sub(self.tolerance)..=nominal + self.tolerance)
+            .map(|start| {
+                let score: f32 = natural
+                    .iter()
+                    .enumerate()
+                    .map(|(index, sample)| sample * self.mono(start + index * SEARCH_STEP))
+                    .sum();
+                (start, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(nominal, |(start, _)| start)
+    }
+    #[doc = "Overlap-add one frame, false once the input is exhausted"]
+    fn stretch(&mut self, source: &mut impl Iterator<Item = f32>) -> bool {
+        let nominal = self.position.round() as usize;
+        let needed = nominal + self.tolerance + self.frame;
+        while !self.input_done && self.input.len() < needed {
+            for channel in 0..self.channels {
+                match source.next() {
+                    Some(sample) => self.input.push(channel, sample),
+                    None => {
+                        self.input_done = true;
+                        (channel..self.channels).for_each(|channel| self.input.push(channel, 0.));
+                        break;
+                    }
+                }
+            }
+        }
+        if self.input_done && nominal >= self.input.len() {
+            return false;
+        }
+        let start = self.best_offset(nominal);
+        for channel in 0..self.channels {
+            let frame: Vec<f32> = (0..self.frame)
+                .map(|index| self.input.get(channel, start + index) * self.window[index])
+                .collect();
+            for (sample, tail) in frame[..self.hop].iter().zip(self.tail[channel].iter()) {
+                let sample = self
+                    .anti_alias
+                    .iter_mut()
+                    .fold(sample + tail, |sample, filter| {
+                        filter.process(channel, sample)
+                    });
+                self.stretched.push(channel, sample);
+            }
+            self.tail[channel].copy_from_slice(&frame[self.hop..]);
+        }
+        let continuation = start + self.hop;
+        self.position += self.hop as f64 / self.ratio;
+        // Drop the input neither the next search nor the continuation can reach
+        let consumed = (self.position.floor() as usize)
+            .saturating_sub(self.tolerance)
+            .min(continuation)
+            .min(self.input.len());
+        self.input.consume(consumed);
+        self.position -= consumed as f64;
+        self.continuation = Some(continuation - consumed);
+        true
+    }
+    #[doc = "Resample the stretched frames by `ratio` into interleaved output, false once everything was output"]
+    fn fill(&mut self, source: &mut impl Iterator<Item = f32>) -> bool {
+        while self.read as usize + 1 >= self.stretched.len() {
+            if !self.stretch(source) {
+                return false;
+            }
+        }
+        let index = self.read as usize;
+        let fraction = (self.read - index as f64) as f32;
+        for channel in 0..self.channels {
+            let (current, next) = (
+                self.stretched.get(channel, index),
+                self.stretched.get(channel, index + 1),
+            );
+            self.output.push_back(current + (next - current) * fraction);
+        }
+        self.read += self.ratio;
+        let consumed = (self.read as usize).min(self.stretched.len());
+        self.stretched.consume(consumed);
+        self.read -= consumed as f64;
+        true
+    }
+}
+
+pub struct PitchSource<I> {
+    input: I,
+    shared: Arc<Mutex<f32>>,
+    ratio: f32,
+    #[doc = "None while the ratio stayed 1 since the start or the last seek, the samples are then passed through"]
+    shifter: Option<Shifter>,
+    #[doc = "Channel of the next sample, the ratio only changes between two frames"]
+    channel: usize,
+    until_check: usize,
+}
+
+impl<I: Source> PitchSource<I> {
+    fn poll_ratio(&mut self) {
+        let Ok(shared) = self.shared.try_lock() else {
+            return;
+        };
+        if *shared == self.ratio {
+            return;
+        }
+        self.ratio = *shared;
+        // An existing shifter keeps its buffers so the change does not skip or repeat audio
+        match self.shifter.as_mut() {
+            Some(shifter) => shifter.set_ratio(self.ratio),
+            None => {
+                self.shifter = (self.ratio != 1.).then(|| {
+                    Shifter::new(
+                        self.input.channels().max(1) as usize,
+                        self.input.sample_rate(),
+                        self.ratio,
+                    )
+                })
+            }
+        }
+    }
+}
+
+impl<I: Source> Iterator for PitchSource<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_check == 0 && self.channel == 0 {
+            self.poll_ratio();
+            self.until_check = CHECK_INTERVAL;
+        }
+        self.until_check = self.until_check.saturating_sub(1);
+        let channels = self.input.channels().max(1) as usize;
+        self.channel = (self.channel + 1) % channels;
+        let Some(shifter) = self.shifter.as_mut() else {
+            return self.input.next();
+        };
+        if shifter.output.is_empty() && !shifter.fill(&mut self.input) {
+            return None;
+        }
+        shifter.output.pop_front()
+    }
+}
+
+impl<I: Source> Source for PitchSource<I> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+    fn channels(&self) -> rodio::ChannelCount {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.input.try_seek(pos)?;
+        // The buffered frames belong to the old position
+        if let Some(channels) = self.shifter.as_ref().map(|shifter| shifter.channels) {
+            self.shifter = (self.ratio != 1.)
+                .then(|| Shifter::new(channels, self.input.sample_rate(), self.ratio));
+        }
+        self.channel = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use symphonia::core::{
+    codecs::CODEC_TYPE_NULL,
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+#[doc = "Extensions of the formats the decoder is built with, they rank files but never accept one without probing it"]
+const AUDIO_EXTENSIONS: [&str; 15] = [
+    "aac", "aif", "aifc", "aiff", "caf", "flac", "m4a", "mka", "mp1", "mp2", "mp3", "oga", "ogg",
+    "wav", "wave",
+];
+
+#[doc = "Extensions of files commonly found next to audio, rejected without opening them"]
+const OTHER_EXTENSIONS: [&str; 14] = [
+    "bmp", "cue", "gif", "jpeg", "jpg", "log", "m3u", "m3u8", "md", "nfo", "pdf", "pls", "png",
+    "txt",
+];
+
+#[doc = "Extensions of Opus, WavPack and Monkey's Audio files, symphonia has no decoder for them so they are rejected without opening them"]
+const UNSUPPORTED_EXTENSIONS: [&str; 3] = ["ape", "opus", "wv"];
+
+pub fn has_audio_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        AUDIO_EXTENSIONS
+            .iter()
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    })
+}
+
+#[doc = "Keep `path` when it is a file the decoder can play. Its contents are probed, the extension only hints the probe and rejects the files listed above, so a renamed or truncated file is caught here rather than at playback"]
+pub fn verify_audio_file(path: &Path) -> Option<PathBuf> {
+    let rejected = path.extension().is_some_and(|extension| {
+        OTHER_EXTENSIONS
+            .iter()
+            .chain(&UNSUPPORTED_EXTENSIONS)
+            .any(|known| extension.eq_ignore_ascii_case(known))
+    });
+    if rejected {
+        return None;
+    }
+    let file = std::fs::File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    let stream = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(&extension.to_lowercase());
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    // The decoder plays the first track that has a codec, as rodio does
+    let codec = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?
+        .codec_params
+        .codec;
+    symphonia::default::get_codecs()
+        .get_codec(codec)
+        .map(|_| path.to_path_buf())
+}
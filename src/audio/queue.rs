@@ -0,0 +1,507 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{Arc, Mutex, mpsc::Sender},
+};
+
+use super::{Track, read_artist};
+
+#[doc = "Tracks kept in the history, the oldest are forgotten first"]
+const HISTORY_LIMIT: usize = 1000;
+
+#[doc = "What plays once a track ends"]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Repeat {
+    #[default]
+    Off,
+    #[doc = "Play the current track again"]
+    One,
+    #[doc = "Start the queue over after its last track"]
+    All,
+}
+
+impl Repeat {
+    pub fn next(&self) -> Self {
+        match self {
+            Repeat::Off => Repeat::One,
+            Repeat::One => Repeat::All,
+            Repeat::All => Repeat::Off,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Repeat::Off => "Off",
+            Repeat::One => "One",
+            Repeat::All => "All",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Shuffle {
+    #[default]
+    Off,
+    Random,
+    #[doc = "Random order avoiding two tracks of the same artist in a row"]
+    Smart,
+}
+
+impl Shuffle {
+    pub fn next(&self) -> Self {
+        match self {
+            Shuffle::Off => Shuffle::Random,
+            Shuffle::Random => Shuffle::Smart,
+            Shuffle::Smart => Shuffle::Off,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shuffle::Off => "Off",
+            Shuffle::Random => "Random",
+            Shuffle::Smart => "Smart",
+        }
+    }
+}
+
+#[doc = "Artist tags of the files of the queue, read one after the other by a background thread so that shuffling never waits on them"]
+#[derive(Debug, Default, Clone)]
+struct Artists {
+    known: Arc<Mutex<HashMap<PathBuf, Option<String>>>>,
+    #[doc = "Files waiting to be read, the thread stops once the queue is dropped"]
+    reader: Option<Sender<PathBuf>>,
+}
+
+impl Artists {
+    #[doc = "Read the artist of the tracks in the background, CUE tracks have theirs in the sheet"]
+    fn request(&mut self, tracks: &[Track]) {
+        let reader = self.reader.get_or_insert_with(|| {
+            let (sender, receiver) = std::sync::mpsc::channel::<PathBuf>();
+            let known = self.known.clone();
+            std::thread::spawn(move || {
+                for path in receiver {
+                    if known.lock().is_ok_and(|known| known.contains_key(&path)) {
+                        continue;
+                    }
+                    let artist = read_artist(&path);
+                    if let Ok(mut known) = known.lock() {
+                        known.insert(path, artist);
+                    }
+                }
+            });
+            sender
+        });
+        for track in tracks.iter().filter(|track| track.cue.is_none()) {
+            let _ = reader.send(track.path.clone());
+        }
+    }
+    #[doc = "Artist of a track, None when it has none or it was not read yet"]
+    fn get(&self, track: &Track) -> Option<String> {
+        match &track.cue {
+            Some(cue) => cue.performer.clone(),
+            None => self
+                .known
+                .lock()
+                .ok()
+                .and_then(|known| known.get(&track.path).cloned().flatten()),
+        }
+    }
+}
+
+#[doc = "Move tracks of the artist of the previous one further down when another artist can take their place, tracks whose artist is not known yet stay in place"]
+fn spread_artists(tracks: &mut [Track], known: &Artists) {
+    let mut artists: Vec<Option<String>> = tracks.iter().map(|track| known.get(track)).collect();
+    for index in 1..tracks.len() {
+        if artists[index].is_none() || artists[index] != artists[index - 1] {
+            continue;
+        }
+        if let Some(other) =
+            (index + 1..tracks.len()).find(|other| artists[*other] != artists[index - 1])
+        {
+            tracks.swap(index, other);
+            artists.swap(index, other);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Queue {
+    #[doc = "Tracks in playing order, shuffled or not"]
+    tracks: Vec<Track>,
+    #[doc = "Index of the playing track, the length of the queue once the playing track was removed from its end so that nothing plays until tracks are added or the cursor moves"]
+    current: Option<usize>,
+    repeat: Repeat,
+    shuffle: Shuffle,
+    #[doc = "Order of the tracks before they were shuffled, restored when shuffle is turned off"]
+    unshuffled: Option<Vec<Track>>,
+    #[doc = "Tracks played before the current one, the last played at the end"]
+    history: VecDeque<Track>,
+    artists: Artists,
+}
+
+impl Queue {
+    pub fn from(tracks: Vec<Track>) -> Self {
+        let mut artists = Artists::default();
+        artists.request(&tracks);
+        Self {
+            current: (!tracks.is_empty()).then_some(0),
+            tracks,
+            artists,
+            ..Self::default()
+        }
+    }
+    #[doc = "Queue of `tracks` with the cursor on the track at `start`, or on the first one when it is out of range"]
+    pub fn starting_at(tracks: Vec<Track>, start: usize) -> Self {
+        let mut queue = Self::from(tracks);
+        queue.current = queue.current.map(|_| start.min(queue.tracks.len() - 1));
+        queue
+    }
+    pub fn push(&mut self, track: Track) {
+        if let Some(unshuffled) = &mut self.unshuffled {
+            unshuffled.push(track.clone());
+        }
+        self.artists.request(std::slice::from_ref(&track));
+        self.tracks.push(track);
+    }
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+    pub fn current_index(&self) -> Option<usize> {
+        self.current.filter(|current| *current < self.tracks.len())
+    }
+    pub fn current(&self) -> Option<&Track> {
+        self.tracks.get(self.current?)
+    }
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = repeat;
+    }
+    pub fn shuffle(&self) -> Shuffle {
+        self.shuffle
+    }
+    #[doc = "Shuffle the tracks after the current one, which moves first, or restore their order when `shuffle` is Off"]
+    pub fn set_shuffle(&mut self, shuffle: Shuffle) {
+        let current = self.current().cloned();
+        let mut tracks = self
+            .unshuffled
+            .take()
+            .unwrap_or_else(|| self.tracks.clone());
+        let position = |tracks: &[Track]| {
+            tracks
+                .iter()
+                .position(|track| Some(track) == current.as_ref())
+        };
+        self.shuffle = shuffle;
+        if shuffle != Shuffle::Off {
+            self.unshuffled = Some(tracks.clone());
+            let start = match position(&tracks) {
+                Some(index) => {
+                    let current = tracks.remove(index);
+                    tracks.insert(0, current);
+                    1
+                }
+                None => 0,
+            };
+            fastrand::shuffle(&mut tracks[start..]);
+            (shuffle == Shuffle::Smart)
+                .then(|| spread_artists(&mut tracks[start.saturating_sub(1)..], &self.artists));
+        }
+        // The cursor stays past the end when the playing track was removed
+        if current.is_some() {
+            self.current = position(&tracks);
+        }
+        self.tracks = tracks;
+    }
+    #[doc = "Index of the track following the current one, wrapping around when repeating the whole queue"]
+    fn next_index(&self) -> Option<usize> {
+        let next = self.current.map_or(0, |i| i + 1);
+        match self.repeat {
+            _ if next < self.tracks.len() => Some(next),
+            Repeat::All if !self.tracks.is_empty() => Some(0),
+            _ => None,
+        }
+    }
+    #[doc = "Track that plays once the current one ends"]
+    pub fn peek_next(&self) -> Option<&Track> {
+        match self.repeat {
+            Repeat::One => self.current(),
+            _ => self.tracks.get(self.next_index()?),
+        }
+    }
+    #[doc = "Move the cursor to the track that plays once the current one ends, returns None at the end of the queue"]
+    pub fn advance(&mut self) -> Option<&Track> {
+        match self.repeat {
+            Repeat::One => self.current(),
+            _ => self.next(),
+        }
+    }
+    #[doc = "Move the cursor to the next track, returns None and leaves the cursor untouched at the end of the queue"]
+    pub fn next(&mut self) -> Option<&Track> {
+        let next = self.next_index()?;
+        self.jump_to(next)
+    }
+    #[doc = "Move the cursor back to the track played before the current one, or to the one before it in the queue when nothing was played yet"]
+    pub fn previous(&mut self) -> Option<&Track> {
+        while let Some(track) = self.history.pop_back() {
+            if let Some(index) = self.tracks.iter().position(|other| *other == track) {
+                self.current = Some(index);
+                return self.current();
+            }
+        }
+        let previous = self.current?.checked_sub(1)?;
+        self.current = Some(previous);
+        self.current()
+    }
+    pub fn jump_to(&mut self, index: usize) -> Option<&Track> {
+        if index >= self.tracks.len() {
+            return None;
+        }
+        self.history.extend(self.current().cloned());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.current = Some(index);
+        self.current()
+    }
+    #[doc = "Remove a track, keeping the cursor on the same track when it is not the removed one. Removing the playing track moves the cursor to the track after it, past the end when it was the last one unless the whole queue repeats"]
+    pub fn remove(&mut self, index: usize) -> Option<Track> {
+        if index >= self.tracks.len() {
+            return None;
+        }
+        let removed = self.tracks.remove(index);
+        if let Some(unshuffled) = &mut self.unshuffled
+            && let Some(position) = unshuffled.iter().position(|track| *track == removed)
+        {
+            unshuffled.remove(position);
+        }
+        self.current = match self.current {
+            _ if self.tracks.is_empty() => None,
+            Some(c) if index < c => Some(c - 1),
+            Some(c) if index == c && c >= self.tracks.len() => match self.repeat {
+                Repeat::All => Some(0),
+                _ => Some(self.tracks.len()),
+            },
+            c => c,
+        };
+        Some(removed)
+    }
+    #[doc = "Move a track from one position to another, the cursor follows the track it was pointing to. While shuffled the track also moves after the one it now follows in the order restored when shuffle is turned off"]
+    pub fn move_track(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tracks.len() || to >= self.tracks.len() {
+            return false;
+        }
+        let track = self.tracks.remove(from);
+        if let Some(unshuffled) = &mut self.unshuffled
+            && let Some(position) = unshuffled.iter().position(|other| *other == track)
+        {
+            let moved = unshuffled.remove(position);
+            let after = to
+                .checked_sub(1)
+                .and_then(|before| {
+                    unshuffled
+                        .iter()
+                        .position(|other| *other == self.tracks[before])
+                })
+                .map_or(0, |before| before + 1);
+            unshuffled.insert(after, moved);
+        }
+        self.tracks.insert(to, track);
+        self.current = self.current.map(|c| match c {
+            c if c == from => to,
+            c if from < c && c <= to => c - 1,
+            c if to <= c && c < from => c + 1,
+            c => c,
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[doc = "Track of the file `<number>.mp3`"]
+    fn track(number: usize) -> Track {
+        Track::from(PathBuf::from(format!("{number}.mp3")))
+    }
+
+    fn numbered(count: usize) -> Queue {
+        Queue::from((0..count).map(track).collect())
+    }
+
+    #[doc = "Numbers of the tracks of the queue in playing order"]
+    fn order(queue: &Queue) -> Vec<usize> {
+        queue.tracks().iter().map(number).collect()
+    }
+
+    fn number(track: &Track) -> usize {
+        track
+            .path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.parse().ok())
+            .unwrap()
+    }
+
+    #[test]
+    fn repeat_decides_what_follows_the_last_track() {
+        let mut queue = numbered(3);
+        queue.jump_to(2);
+        assert!(queue.peek_next().is_none());
+        assert!(queue.advance().is_none());
+        assert_eq!(queue.current_index(), Some(2));
+        queue.set_repeat(Repeat::One);
+        assert_eq!(queue.peek_next().map(number), Some(2));
+        assert_eq!(queue.advance().map(number), Some(2));
+        assert_eq!(queue.next().map(number), None);
+        queue.set_repeat(Repeat::All);
+        assert_eq!(queue.peek_next().map(number), Some(0));
+        assert_eq!(queue.advance().map(number), Some(0));
+    }
+
+    #[test]
+    fn shuffle_moves_the_current_track_first_and_off_restores_the_order() {
+        let mut queue = numbered(20);
+        queue.jump_to(5);
+        queue.set_shuffle(Shuffle::Random);
+        assert_eq!(queue.current_index(), Some(0));
+        assert_eq!(queue.current().map(number), Some(5));
+        let mut sorted = order(&queue);
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+        queue.next();
+        let playing = queue.current().map(number);
+        queue.set_shuffle(Shuffle::Off);
+        assert_eq!(order(&queue), (0..20).collect::<Vec<_>>());
+        assert_eq!(queue.current().map(number), playing);
+    }
+
+    #[test]
+    fn tracks_added_or_removed_while_shuffled_survive_turning_it_off() {
+        let mut queue = numbered(5);
+        queue.set_shuffle(Shuffle::Random);
+        queue.push(track(5));
+        let index = queue.tracks().iter().position(|track| number(track) == 2);
+        queue.remove(index.unwrap());
+        queue.set_shuffle(Shuffle::Off);
+        assert_eq!(order(&queue), [0, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn previous_follows_the_history_then_the_queue() {
+        let mut queue = numbered(5);
+        queue.jump_to(3);
+        queue.jump_to(1);
+        assert_eq!(queue.previous().map(number), Some(3));
+        assert_eq!(queue.previous().map(number), Some(0));
+        assert!(queue.previous().is_none());
+        assert_eq!(queue.current_index(), Some(0));
+        queue.jump_to(4);
+        // Track 0 left the queue, the track before the current one plays instead
+        queue.remove(0);
+        assert_eq!(queue.previous().map(number), Some(3));
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut queue = numbered(2);
+        (0..HISTORY_LIMIT + 10).for_each(|index| {
+            queue.jump_to(index % 2);
+        });
+        assert_eq!(queue.history.len(), HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn remove_keeps_the_cursor_on_the_playing_track() {
+        let mut queue = numbered(5);
+        queue.jump_to(2);
+        assert_eq!(queue.remove(0).as_ref().map(number), Some(0));
+        assert_eq!(queue.current().map(number), Some(2));
+        queue.remove(3);
+        assert_eq!(queue.current().map(number), Some(2));
+        assert!(queue.remove(3).is_none());
+        queue.remove(1);
+        assert_eq!(order(&queue), [1, 3]);
+        assert_eq!(queue.current().map(number), Some(3));
+    }
+
+    #[test]
+    fn removing_the_playing_last_track_ends_the_queue() {
+        let mut queue = numbered(3);
+        queue.jump_to(2);
+        queue.remove(2);
+        assert_eq!(queue.current_index(), None);
+        assert!(queue.advance().is_none());
+        queue.set_repeat(Repeat::One);
+        assert!(queue.advance().is_none());
+        queue.set_shuffle(Shuffle::Random);
+        assert_eq!(queue.current_index(), None);
+        queue.set_shuffle(Shuffle::Off);
+        queue.push(track(3));
+        assert_eq!(queue.current().map(number), Some(3));
+
+        let mut queue = numbered(3);
+        queue.set_repeat(Repeat::All);
+        queue.jump_to(2);
+        queue.remove(2);
+        assert_eq!(queue.current().map(number), Some(0));
+
+        let mut queue = numbered(1);
+        queue.remove(0);
+        assert_eq!(queue.current_index(), None);
+        assert_eq!(queue.advance(), None);
+        queue.push(track(1));
+        assert_eq!(queue.advance().map(number), Some(1));
+    }
+
+    #[test]
+    fn move_track_keeps_the_cursor_on_the_playing_track() {
+        let mut queue = numbered(5);
+        queue.jump_to(2);
+        assert!(queue.move_track(2, 4));
+        assert_eq!(order(&queue), [0, 1, 3, 4, 2]);
+        assert_eq!(queue.current_index(), Some(4));
+        assert!(queue.move_track(0, 4));
+        assert_eq!(queue.current_index(), Some(3));
+        assert!(queue.move_track(4, 0));
+        assert_eq!(order(&queue), [0, 1, 3, 4, 2]);
+        assert_eq!(queue.current_index(), Some(4));
+        assert!(!queue.move_track(5, 0));
+        assert!(!queue.move_track(0, 5));
+    }
+
+    #[test]
+    fn moves_made_while_shuffled_survive_turning_it_off() {
+        let mut queue = numbered(6);
+        queue.set_shuffle(Shuffle::Random);
+        let shuffled = order(&queue);
+        // The last track of the shuffled order moves right after the first one
+        queue.move_track(5, 1);
+        let (first, moved) = (shuffled[0], shuffled[5]);
+        queue.set_shuffle(Shuffle::Off);
+        let restored = order(&queue);
+        let position = |number| restored.iter().position(|other| *other == number).unwrap();
+        assert_eq!(position(moved), position(first) + 1);
+        let mut others: Vec<usize> = restored
+            .into_iter()
+            .filter(|other| *other != moved)
+            .collect();
+        others.sort();
+        assert!(others.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let mut queue = queue_shuffled_moved_first(6);
+        queue.set_shuffle(Shuffle::Off);
+        assert_eq!(
+            order(&queue)[0],
+            queue.tracks().iter().map(number).next().unwrap()
+        );
+    }
+
+    #[doc = "Shuffled queue whose third track was moved to the top"]
+    fn queue_shuffled_moved_first(count: usize) -> Queue {
+        let mut queue = numbered(count);
+        queue.set_shuffle(Shuffle::Random);
+        queue.move_track(2, 0);
+        queue
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use rodio::{Source, source::UniformSourceIterator};
+
+use super::{
+    EqSettings, Equalizer, FilterSettings, Filters, ReplayGainMode, ReplayGainSettings,
+    SPEED_RANGE, VOLUME_RANGE, open_source, pitch::PitchShift, pitch_ratio,
+    replay_gain::ReplayGain,
+};
+use crate::error::{Error, Result};
+
+#[doc = "Settings applied to an offline render, the same ones the player applies live"]
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub low_pass: Option<u32>,
+    pub high_pass: Option<u32>,
+    pub equalizer: EqSettings,
+    pub replay_gain: ReplayGainSettings,
+    pub speed: f32,
+    #[doc = "Pitch shift in semitones"]
+    pub semitones: i32,
+    #[doc = "Keep the pitch when the speed is not 1"]
+    pub preserve_pitch: bool,
+    pub volume: f32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            low_pass: None,
+            high_pass: None,
+            equalizer: EqSettings::default(),
+            replay_gain: ReplayGainSettings::default(),
+            speed: 1.,
+            semitones: 0,
+            preserve_pitch: true,
+            volume: 1.,
+        }
+    }
+}
+
+#[doc = "`value` clamped to `range`, 1 when it is not a number"]
+fn bounded(value: f32, (min, max): (f32, f32)) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        1.
+    }
+}
+
+#[doc = "Decode `input` through the playback pipeline and write the result to `output` as a 32 bit float WAV, returns the number of samples written"]
+pub fn render_to_wav(input: &PathBuf, output: &PathBuf, options: RenderOptions) -> Result<u64> {
+    let filters = Filters::default();
+    filters.set(FilterSettings::from(options.low_pass, options.high_pass));
+    let equalizer = Equalizer::default();
+    equalizer.set(options.equalizer);
+    let gain = ReplayGain::read(input).factor(
+        options.replay_gain,
+        options.replay_gain.mode == ReplayGainMode::Album,
+    );
+    // A speed of 0 or below would make the pitch ratio infinite or negative
+    let speed = bounded(options.speed, SPEED_RANGE);
+    let volume = bounded(options.volume, VOLUME_RANGE);
+    let pitch = PitchShift::default();
+    pitch.set(pitch_ratio(
+        speed,
+        options.semitones,
+        options.preserve_pitch,
+    ));
+    let source = open_source(input, gain, &pitch, &filters, &equalizer)?;
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
+    // Speed only scales the reported sample rate, resampling back to the input rate makes the file shorter instead
+    let source =
+        UniformSourceIterator::new(source.speed(speed), channels, sample_rate).amplify(volume);
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let encode_error = |e| Error::Encode(output.clone(), e);
+    let mut writer = hound::WavWriter::create(output, spec).map_err(encode_error)?;
+    let mut written = 0;
+    for sample in source {
+        writer.write_sample(sample).map_err(encode_error)?;
+        written += 1;
+    }
+    writer.finalize().map_err(encode_error)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[doc = "One second of a 440 Hz sine at 44.1 kHz"]
+    fn sine(path: &PathBuf) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for index in 0..44100 {
+            let phase = index as f32 * 440. * std::f32::consts::TAU / 44100.;
+            writer.write_sample((phase.sin() * 8000.) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn speed_keeps_the_sample_rate_and_shortens_the_file() {
+        let dir = std::env::temp_dir().join(format!("render-{}-speed", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("input.wav"), dir.join("output.wav"));
+        sine(&input);
+        for (speed, preserve_pitch) in [(1.5, false), (0.5, false), (1.5, true), (0.5, true)] {
+            let options = RenderOptions {
+                speed,
+                preserve_pitch,
+                ..RenderOptions::default()
+            };
+            let written = render_to_wav(&input, &output, options).unwrap();
+            let reader = hound::WavReader::open(&output).unwrap();
+            assert_eq!(reader.spec().sample_rate, 44100);
+            assert_eq!(reader.spec().channels, 1);
+            assert_eq!(u64::from(reader.duration()), written);
+            // Keeping the pitch flushes the last window of the shifter after the input ends
+            let expected = 44100. / speed;
+            assert!(
+                (written as f32 - expected).abs() < 1024.,
+                "{speed} {preserve_pitch}: {written} samples instead of {expected}"
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use lofty::{file::TaggedFileExt, tag::ItemKey};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+    #[doc = "Album gain while the queue plays an album in order, track gain otherwise"]
+    Auto,
+}
+
+impl ReplayGainMode {
+    pub fn next(&self) -> Self {
+        match self {
+            ReplayGainMode::Off => ReplayGainMode::Track,
+            ReplayGainMode::Track => ReplayGainMode::Album,
+            ReplayGainMode::Album => ReplayGainMode::Auto,
+            ReplayGainMode::Auto => ReplayGainMode::Off,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "Off",
+            ReplayGainMode::Track => "Track",
+            ReplayGainMode::Album => "Album",
+            ReplayGainMode::Auto => "Auto",
+        }
+    }
+}
+
+#[doc = "How ReplayGain is applied, `preamp` in dB is added to the tag gain"]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    pub preamp: f32,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::default(),
+            preamp: 0.,
+        }
+    }
+}
+
+#[doc = "Difference in dB between the ReplayGain 2.0 reference (-18 LUFS) and the R128 one (-23 LUFS)"]
+const R128_OFFSET: f32 = 5.;
+
+#[doc = "Gains in dB relative to the ReplayGain 2.0 reference and linear peaks of a file"]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+#[doc = "Parse `-6.54 dB` or `0.988`"]
+fn parse_value(value: &str) -> Option<f32> {
+    value
+        .split_whitespace()
+        .next()?
+        .trim_end_matches("dB")
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+#[doc = "Parse an R128 gain, a Q7.8 fixed point number relative to -23 LUFS"]
+fn parse_r128(value: &str) -> Option<f32> {
+    Some(value.trim().parse::<i16>().ok()? as f32 / 256. + R128_OFFSET)
+}
+
+impl ReplayGain {
+    #[doc = "Read the REPLAYGAIN_* tags, or the R128_*_GAIN ones Opus files use, empty when the file has none"]
+    pub fn read(path: &PathBuf) -> Self {
+        let Ok(tagged_file) = lofty::read_from_path(path) else {
+            return Self::default();
+        };
+        let Some(tag) = tagged_file.primary_tag().or(tagged_file.first_tag()) else {
+            return Self::default();
+        };
+        let value = |key: ItemKey| tag.get_string(&key).and_then(parse_value);
+        let r128 = |key: &str| {
+            tag.get_string(&ItemKey::Unknown(key.to_string()))
+                .and_then(parse_r128)
+        };
+        Self {
+            track_gain: value(ItemKey::ReplayGainTrackGain).or_else(|| r128("R128_TRACK_GAIN")),
+            track_peak: value(ItemKey::ReplayGainTrackPeak),
+            album_gain: value(ItemKey::ReplayGainAlbumGain).or_else(|| r128("R128_ALBUM_GAIN")),
+            album_peak: value(ItemKey::ReplayGainAlbumPeak),
+        }
+    }
+    #[doc = "Linear factor to apply, lowered so that the stored peak does not clip"]
+    pub fn factor(&self, settings: ReplayGainSettings, album: bool) -> f32 {
+        let (gain, peak) = match (settings.mode, album) {
+            (ReplayGainMode::Off, _) => return 1.,
+            (ReplayGainMode::Album, _) | (ReplayGainMode::Auto, true) => (
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            ),
+            (ReplayGainMode::Track, _) | (ReplayGainMode::Auto, false) => (
+                self.track_gain.or(self.album_gain),
+                self.track_peak.or(self.album_peak),
+            ),
+        };
+        let Some(gain) = gain else {
+            return 1.;
+        };
+        let factor = 10f32.powf((gain + settings.preamp) / 20.);
+        match peak.filter(|peak| *peak > 0.) {
+            Some(peak) => factor.min(1. / peak),
+            None => factor,
+        }
+    }
+}
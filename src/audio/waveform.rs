@@ -0,0 +1,214 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, Weak, mpsc::Sender},
+    time::SystemTime,
+};
+
+use crate::error::{Error, Result};
+
+#[doc = "Buckets of a waveform overview, enough for two columns per cell on a wide terminal"]
+const RESOLUTION: usize = 1024;
+
+#[doc = "Samples summarized by one entry before the track is resampled to `RESOLUTION` buckets"]
+const CHUNK: usize = 1024;
+
+#[doc = "Minimum and maximum sample of evenly sized slices of a whole track"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub buckets: Vec<(f32, f32)>,
+}
+
+impl Waveform {
+    #[doc = "Decode the whole file and summarize it in `RESOLUTION` buckets, None when `wanted` turned false on the way"]
+    fn compute(path: &PathBuf, wanted: &dyn Fn() -> bool) -> Result<Option<Self>> {
+        let file = File::open(path).map_err(|e| Error::Io(path.clone(), e))?;
+        let decoder = rodio::Decoder::new(BufReader::new(file))
+            .map_err(|e| Error::Decode(path.clone(), e))?;
+        let mut chunks = Vec::new();
+        let (mut min, mut max, mut count) = (0f32, 0f32, 0);
+        for sample in decoder {
+            (min, max, count) = (min.min(sample), max.max(sample), count + 1);
+            if count == CHUNK {
+                chunks.push((min, max));
+                (min, max, count) = (0., 0., 0);
+                if chunks.len() % CANCEL_INTERVAL == 0 && !wanted() {
+                    return Ok(None);
+                }
+            }
+        }
+        (count > 0).then(|| chunks.push((min, max)));
+        if chunks.is_empty() {
+            return Ok(Some(Self {
+                buckets: Vec::new(),
+            }));
+        }
+        let buckets = (0..RESOLUTION)
+            .map(|bucket| {
+                let start = bucket * chunks.len() / RESOLUTION;
+                let end = ((bucket + 1) * chunks.len() / RESOLUTION).max(start + 1);
+                chunks[start..end.min(chunks.len())]
+                    .iter()
+                    .fold((0f32, 0f32), |(min, max), chunk| {
+                        (min.min(chunk.0), max.max(chunk.1))
+                    })
+            })
+            .collect();
+        Ok(Some(Self { buckets }))
+    }
+    #[doc = "Highest absolute sample, used to scale the overview"]
+    pub fn peak(&self) -> f32 {
+        self.buckets
+            .iter()
+            .fold(0., |peak, (min, max)| peak.max(-min).max(*max))
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        self.buckets
+            .iter()
+            .flat_map(|(min, max)| min.to_le_bytes().into_iter().chain(max.to_le_bytes()))
+            .collect()
+    }
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        (bytes.len() == RESOLUTION * 8 || bytes.is_empty()).then(|| Self {
+            buckets: bytes
+                .chunks_exact(8)
+                .map(|pair| {
+                    let value = |range: std::ops::Range<usize>| {
+                        f32::from_le_bytes(pair[range].try_into().unwrap_or_default())
+                    };
+                    (value(0..4), value(4..8))
+                })
+                .collect(),
+        })
+    }
+}
+
+#[doc = "Overviews kept in the cache, the least recently used are removed beyond it"]
+const CACHE_LIMIT: usize = 2000;
+
+#[doc = "Chunks decoded between two checks that the overview is still wanted"]
+const CANCEL_INTERVAL: usize = 256;
+
+#[doc = "FNV-1a hash of the path, size and modification time of a file, stable across runs unlike the std hasher"]
+fn cache_key(path: &Path) -> Result<u64> {
+    let metadata = path
+        .metadata()
+        .map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos());
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    Ok(path
+        .to_string_lossy()
+        .bytes()
+        .chain(metadata.len().to_le_bytes())
+        .chain(modified.to_le_bytes())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        }))
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("TUIAudioPlayer").join("waveforms"))
+}
+
+#[doc = "Remove the least recently used overviews until `CACHE_LIMIT` are left"]
+fn evict(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut cached: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    if cached.len() <= CACHE_LIMIT {
+        return;
+    }
+    cached.sort();
+    let excess = cached.len() - CACHE_LIMIT;
+    for (_, path) in cached.into_iter().take(excess) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[doc = "Read the overview from the cache, or compute it and store it there. None when `wanted` turned false before it was computed"]
+fn load(path: &PathBuf, wanted: &dyn Fn() -> bool) -> Result<Option<Waveform>> {
+    let dir = cache_dir();
+    let cache = dir
+        .as_ref()
+        .zip(cache_key(path).ok())
+        .map(|(dir, key)| dir.join(format!("{key:016x}.bin")));
+    if let Some(cache) = &cache
+        && let Some(waveform) = std::fs::read(cache)
+            .ok()
+            .and_then(|bytes| Waveform::from_bytes(&bytes))
+    {
+        // Marks the overview as recently used for the eviction
+        let _ = File::options()
+            .write(true)
+            .open(cache)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        return Ok(Some(waveform));
+    }
+    let Some(waveform) = Waveform::compute(path, wanted)? else {
+        return Ok(None);
+    };
+    // The overview is only a cache, failing to store it is not worth an error
+    if let (Some(dir), Some(cache)) = (dir, cache)
+        && std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&cache, waveform.to_bytes()))
+            .is_ok()
+    {
+        evict(&dir);
+    }
+    Ok(Some(waveform))
+}
+
+type Slot = Arc<Mutex<Option<Arc<Waveform>>>>;
+
+#[doc = "File to compute the overview of, and where to put it as long as it is still wanted"]
+type Job = (PathBuf, Weak<Mutex<Option<Arc<Waveform>>>>);
+
+#[doc = "Queue of the thread computing the overviews one after the other, started on first use"]
+fn worker() -> &'static Sender<Job> {
+    static WORKER: OnceLock<Sender<Job>> = OnceLock::new();
+    WORKER.get_or_init(|| {
+        let (sender, receiver) = std::sync::mpsc::channel::<Job>();
+        std::thread::spawn(move || {
+            for (path, slot) in receiver {
+                // Overviews of the tracks skipped past were dropped, they are not computed
+                let wanted = || slot.strong_count() > 0;
+                if !wanted() {
+                    continue;
+                }
+                if let Ok(Some(waveform)) = load(&path, &wanted)
+                    && let Some(slot) = slot.upgrade()
+                    && let Ok(mut slot) = slot.lock()
+                {
+                    *slot = Some(Arc::new(waveform));
+                }
+            }
+        });
+        sender
+    })
+}
+
+#[doc = "Handle on an overview computed in the background, empty until it is ready or when the file cannot be decoded. Dropping it cancels the computation"]
+#[derive(Debug, Clone, Default)]
+pub struct PendingWaveform {
+    slot: Slot,
+}
+
+impl PendingWaveform {
+    pub fn load(path: PathBuf) -> Self {
+        let pending = Self::default();
+        let _ = worker().send((path, Arc::downgrade(&pending.slot)));
+        pending
+    }
+    pub fn get(&self) -> Option<Arc<Waveform>> {
+        self.slot.lock().ok().and_then(|slot| slot.clone())
+    }
+}
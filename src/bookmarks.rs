@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, path::Path, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{read_toml, update_toml};
+use crate::error::Result;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Bookmark {
+    pub name: String,
+    #[doc = "Position in the track in seconds"]
+    pub position: f64,
+}
+
+impl Bookmark {
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.position.max(0.))
+    }
+}
+
+#[doc = "Named positions of every file, saved to `bookmarks.toml` in the user data directory"]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Bookmarks {
+    #[doc = "Bookmarks sorted by position, keyed by the absolute path of the file"]
+    files: BTreeMap<String, Vec<Bookmark>>,
+}
+
+fn key(track: &Path) -> String {
+    track
+        .canonicalize()
+        .unwrap_or_else(|_| track.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+impl Bookmarks {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("TUIAudioPlayer").join("bookmarks.toml"))
+    }
+    #[doc = "Bookmarks of every file, none when the file does not exist"]
+    pub fn load() -> Result<Self> {
+        Self::path().map_or(Ok(Self::default()), |path| read_toml(&path))
+    }
+    #[doc = "Apply `update` to the bookmarks file and write it back"]
+    pub fn persist(update: impl FnOnce(&mut Bookmarks)) -> Result<()> {
+        Self::path().map_or(Ok(()), |path| update_toml(&path, update))
+    }
+    pub fn of(&self, track: &Path) -> &[Bookmark] {
+        self.files.get(&key(track)).map_or(&[], Vec::as_slice)
+    }
+    pub fn add(&mut self, track: &Path, bookmark: Bookmark) {
+        let bookmarks = self.files.entry(key(track)).or_default();
+        bookmarks.push(bookmark);
+        bookmarks.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+    pub fn remove(&mut self, track: &Path, bookmark: &Bookmark) {
+        let key = key(track);
+        if let Some(bookmarks) = self.files.get_mut(&key) {
+            bookmarks.retain(|other| other != bookmark);
+            bookmarks.is_empty().then(|| self.files.remove(&key));
+        }
+    }
+}
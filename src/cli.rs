@@ -1,5 +1,7 @@
 use clap::Subcommand;
 
+use crate::audio::{EqPreset, FadeCurve, ReplayGainMode, SPEED_RANGE, VOLUME_RANGE};
+
 #[derive(Debug, Clone, clap::Parser)]
 #[command(
     author = "Owlyat",
@@ -23,19 +25,128 @@ impl Cli {
     }
 }
 
+#[doc = "Parse a number in `range`, the bounds are the ones the player applies"]
+fn parse_in_range(input: &str, (min, max): (f32, f32)) -> Result<f32, String> {
+    let value: f32 = input.parse().map_err(|e| format!("{e}"))?;
+    (min..=max)
+        .contains(&value)
+        .then_some(value)
+        .ok_or_else(|| format!("must be between {min} and {max}"))
+}
+
+fn parse_speed(input: &str) -> Result<f32, String> {
+    parse_in_range(input, SPEED_RANGE)
+}
+
+fn parse_volume(input: &str) -> Result<f32, String> {
+    parse_in_range(input, VOLUME_RANGE)
+}
+
 #[derive(Debug, Clone, Subcommand)]
 pub enum Command {
     Play {
+        #[doc = "Audio file, CUE sheet or M3U/M3U8, PLS or XSPF playlist"]
         path: std::path::PathBuf,
         #[clap(short = 'L', long = "lowpass")]
         low_pass: Option<u32>,
         #[clap(short = 'H', long = "highpass")]
         high_pass: Option<u32>,
+        #[doc = "Crossfade duration in seconds between tracks of different albums"]
+        #[clap(short = 'x', long = "crossfade")]
+        crossfade: Option<f32>,
+        #[clap(long = "crossfade-curve", value_enum)]
+        crossfade_curve: Option<FadeCurve>,
+        #[doc = "Output device name or index as listed by `devices`"]
+        #[clap(long = "device")]
+        device: Option<String>,
+        #[doc = "Equalizer preset, replaces the bands saved in the config"]
+        #[clap(long = "eq", value_enum)]
+        eq: Option<EqPreset>,
+        #[doc = "ReplayGain mode, replaces the one saved in the config"]
+        #[clap(long = "replay-gain", value_enum)]
+        replay_gain: Option<ReplayGainMode>,
+        #[doc = "Pre-amp in dB added to the ReplayGain of the tags"]
+        #[clap(long = "preamp", allow_negative_numbers = true)]
+        preamp: Option<f32>,
+        #[doc = "Let the pitch follow the speed like a tape instead of preserving it"]
+        #[clap(long = "tape-speed")]
+        tape_speed: bool,
     },
     Player {
+        #[doc = "Directory opened in the explorer, or a playlist to play from its directory"]
         #[clap(short = 'c', long = "CurrentWorkingDirectory")]
         cwd: Option<std::path::PathBuf>,
+        #[clap(short = 'L', long = "lowpass")]
+        low_pass: Option<u32>,
+        #[clap(short = 'H', long = "highpass")]
+        high_pass: Option<u32>,
+        #[doc = "Crossfade duration in seconds between tracks of different albums"]
+        #[clap(short = 'x', long = "crossfade")]
+        crossfade: Option<f32>,
+        #[clap(long = "crossfade-curve", value_enum)]
+        crossfade_curve: Option<FadeCurve>,
+        #[doc = "Output device name or index as listed by `devices`"]
+        #[clap(long = "device")]
+        device: Option<String>,
+        #[doc = "Equalizer preset, replaces the bands saved in the config"]
+        #[clap(long = "eq", value_enum)]
+        eq: Option<EqPreset>,
+        #[doc = "ReplayGain mode, replaces the one saved in the config"]
+        #[clap(long = "replay-gain", value_enum)]
+        replay_gain: Option<ReplayGainMode>,
+        #[doc = "Pre-amp in dB added to the ReplayGain of the tags"]
+        #[clap(long = "preamp", allow_negative_numbers = true)]
+        preamp: Option<f32>,
+        #[doc = "Let the pitch follow the speed like a tape instead of preserving it"]
+        #[clap(long = "tape-speed")]
+        tape_speed: bool,
+    },
+    #[doc = "Decode a file through the playback filters and write the result to a WAV file"]
+    Render {
+        path: std::path::PathBuf,
+        #[clap(short = 'o', long = "output")]
+        output: std::path::PathBuf,
+        #[clap(short = 'L', long = "lowpass")]
+        low_pass: Option<u32>,
+        #[clap(short = 'H', long = "highpass")]
+        high_pass: Option<u32>,
+        #[doc = "Equalizer preset, replaces the bands saved in the config"]
+        #[clap(long = "eq", value_enum)]
+        eq: Option<EqPreset>,
+        #[doc = "ReplayGain mode, replaces the one saved in the config"]
+        #[clap(long = "replay-gain", value_enum)]
+        replay_gain: Option<ReplayGainMode>,
+        #[doc = "Pre-amp in dB added to the ReplayGain of the tags"]
+        #[clap(long = "preamp", allow_negative_numbers = true)]
+        preamp: Option<f32>,
+        #[doc = "Let the pitch follow the speed like a tape instead of preserving it"]
+        #[clap(long = "tape-speed")]
+        tape_speed: bool,
+        #[clap(short = 's', long = "speed", default_value_t = 1., value_parser = parse_speed)]
+        speed: f32,
+        #[doc = "Pitch shift in semitones"]
+        #[clap(
+            short = 'p',
+            long = "pitch",
+            default_value_t = 0,
+            allow_negative_numbers = true
+        )]
+        pitch: i32,
+        #[clap(short = 'v', long = "volume", default_value_t = 1., value_parser = parse_volume)]
+        volume: f32,
+    },
+    #[doc = "Measure the EBU R128 loudness of a file or of the audio files of a directory"]
+    ScanLoudness {
+        path: std::path::PathBuf,
+        #[doc = "Also measure the tracks sharing an album tag as one album"]
+        #[clap(short = 'a', long = "album")]
+        album: bool,
+        #[doc = "Write the ReplayGain 2.0 tags of the measured files"]
+        #[clap(short = 'w', long = "write")]
+        write: bool,
     },
+    #[doc = "List the output devices of every audio host with their supported formats"]
+    Devices,
     TagWritter {
         path: std::path::PathBuf,
         #[clap(short = 't', long = "Title")]
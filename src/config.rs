@@ -0,0 +1,157 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::audio::{
+    AudioPlayer, Crossfade, EqPreset, EqSettings, FadeCurve, FilterSettings, ReplayGainMode,
+    ReplayGainSettings,
+};
+use crate::error::{Error, Result};
+
+#[doc = "Settings read from `config.toml` in the user configuration directory, CLI flags take precedence"]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    #[doc = "Crossfade duration between two tracks in seconds, disabled when absent or 0"]
+    pub crossfade: Option<f32>,
+    pub crossfade_curve: FadeCurve,
+    #[doc = "Seconds skipped by Left/Right"]
+    pub seek_step: u64,
+    #[doc = "Seconds skipped by Shift + Left/Right"]
+    pub seek_step_large: u64,
+    #[doc = "Output device name or index as listed by the `devices` subcommand, the system default when absent"]
+    pub device: Option<String>,
+    #[doc = "Low-pass cutoff in Hz, disabled when absent"]
+    pub low_pass: Option<u32>,
+    #[doc = "High-pass cutoff in Hz, disabled when absent"]
+    pub high_pass: Option<u32>,
+    pub equalizer: EqSettings,
+    pub replay_gain: ReplayGainSettings,
+    #[doc = "Engage the soft limiter once the output clips"]
+    pub auto_limiter: bool,
+    #[doc = "Keep the pitch when the playback speed changes instead of letting it follow like a tape"]
+    pub preserve_pitch: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            crossfade: None,
+            crossfade_curve: FadeCurve::default(),
+            seek_step: 5,
+            seek_step_large: 30,
+            device: None,
+            low_pass: None,
+            high_pass: None,
+            equalizer: EqSettings::default(),
+            replay_gain: ReplayGainSettings::default(),
+            auto_limiter: false,
+            preserve_pitch: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("TUIAudioPlayer").join("config.toml"))
+    }
+    #[doc = "Settings of the config file, the defaults when it does not exist"]
+    pub fn load() -> Result<Self> {
+        Self::path().map_or(Ok(Self::default()), |path| read_toml(&path))
+    }
+    pub fn set_crossfade(&mut self, crossfade: Option<f32>, curve: Option<FadeCurve>) -> &mut Self {
+        crossfade.is_some().then(|| self.crossfade = crossfade);
+        if let Some(curve) = curve {
+            self.crossfade_curve = curve;
+        }
+        self
+    }
+    #[doc = "Apply `update` to the config file and write it back, the CLI overrides of the running config are not saved"]
+    pub fn persist(update: impl FnOnce(&mut Config)) -> Result<()> {
+        Self::path().map_or(Ok(()), |path| update_toml(&path, update))
+    }
+    pub fn set_equalizer_preset(&mut self, preset: Option<EqPreset>) -> &mut Self {
+        if let Some(preset) = preset {
+            self.equalizer = EqSettings::from_preset(preset);
+        }
+        self
+    }
+    pub fn set_replay_gain(
+        &mut self,
+        mode: Option<ReplayGainMode>,
+        preamp: Option<f32>,
+    ) -> &mut Self {
+        if let Some(mode) = mode {
+            self.replay_gain.mode = mode;
+        }
+        if let Some(preamp) = preamp.filter(|preamp| preamp.is_finite()) {
+            self.replay_gain.preamp = preamp;
+        }
+        self
+    }
+    pub fn set_tape_speed(&mut self, tape_speed: bool) -> &mut Self {
+        tape_speed.then(|| self.preserve_pitch = false);
+        self
+    }
+    pub fn set_filters(&mut self, low_pass: Option<u32>, high_pass: Option<u32>) -> &mut Self {
+        low_pass.is_some().then(|| self.low_pass = low_pass);
+        high_pass.is_some().then(|| self.high_pass = high_pass);
+        self
+    }
+    pub fn get_filters(&self) -> FilterSettings {
+        FilterSettings::from(self.low_pass, self.high_pass)
+    }
+    pub fn set_device(&mut self, device: Option<String>) -> &mut Self {
+        device.is_some().then(|| self.device = device);
+        self
+    }
+    #[doc = "Take over the settings changed live on `player`, the next player started from this config keeps them"]
+    pub fn keep_player_settings(&mut self, player: &AudioPlayer) -> &mut Self {
+        let filters = player.filter_settings();
+        let device = player.device_name();
+        (!device.is_empty()).then(|| self.device = Some(device.to_string()));
+        self.low_pass = filters.low_pass.cutoff();
+        self.high_pass = filters.high_pass.cutoff();
+        self.equalizer = player.equalizer_settings();
+        self.replay_gain = player.replay_gain();
+        self.preserve_pitch = player.preserve_pitch();
+        self
+    }
+    pub fn get_crossfade(&self) -> Option<Crossfade> {
+        self.crossfade
+            .filter(|secs| secs.is_finite() && *secs > 0.)
+            .map(|secs| Crossfade {
+                duration: Duration::from_secs_f32(secs),
+                curve: self.crossfade_curve,
+            })
+    }
+}
+
+#[doc = "Contents of the TOML file at `path`, the default when it does not exist yet"]
+pub fn read_toml<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => {
+            toml::from_str(&content).map_err(|e| Error::ConfigRead(path.to_path_buf(), e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(Error::Io(path.to_path_buf(), e)),
+    }
+}
+
+#[doc = "Apply `update` to the TOML file at `path` and write it back, an invalid file is reported and left as it is"]
+pub fn update_toml<T: DeserializeOwned + Serialize + Default>(
+    path: &Path,
+    update: impl FnOnce(&mut T),
+) -> Result<()> {
+    let mut value = read_toml(path)?;
+    update(&mut value);
+    let content =
+        toml::to_string_pretty(&value).map_err(|e| Error::ConfigWrite(path.to_path_buf(), e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| Error::Io(dir.to_path_buf(), e))?;
+    }
+    std::fs::write(path, content).map_err(|e| Error::Io(path.to_path_buf(), e))
+}
@@ -0,0 +1,90 @@
+use std::{fmt, path::Path, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Decode(PathBuf, rodio::decoder::DecoderError),
+    NoDevice,
+    DeviceNotFound(String),
+    Device(rodio::StreamError),
+    TagRead(PathBuf, lofty::error::LoftyError),
+    TagWrite(PathBuf, lofty::error::LoftyError),
+    #[doc = "Settings or bookmarks file that is not valid TOML for what it holds"]
+    ConfigRead(PathBuf, toml::de::Error),
+    ConfigWrite(PathBuf, toml::ser::Error),
+    #[doc = "File that is not audio the decoder can play, or a playlist in a format that cannot be read or written"]
+    UnsupportedFormat(PathBuf),
+    EmptyPlaylist(PathBuf),
+    #[doc = "Entries of a playlist that were not found, the others are playing"]
+    MissingEntries(PathBuf, usize),
+    Encode(PathBuf, hound::Error),
+    Prompt(inquire::InquireError),
+    Terminal(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[doc = "File name of `path`, or the whole path when it has none"]
+fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "cannot open {}: {e}", name(path)),
+            Error::Decode(path, e) => write!(f, "cannot decode {}: {e}", name(path)),
+            Error::NoDevice => write!(f, "no audio output device found"),
+            Error::DeviceNotFound(selector) => write!(f, "no output device matches {selector}"),
+            Error::Device(e) => write!(f, "cannot use audio device: {e}"),
+            Error::TagRead(path, e) => write!(f, "cannot read tags of {}: {e}", name(path)),
+            Error::TagWrite(path, e) => write!(f, "cannot write tags to {}: {e}", name(path)),
+            Error::ConfigRead(path, e) => write!(f, "invalid {}: {e}", path.to_string_lossy()),
+            Error::ConfigWrite(path, e) => write!(f, "cannot write {}: {e}", name(path)),
+            Error::UnsupportedFormat(path) => write!(f, "unsupported format: {}", name(path)),
+            Error::EmptyPlaylist(path) => write!(f, "no playable track in {}", name(path)),
+            Error::MissingEntries(path, count) => {
+                write!(f, "{count} entries of {} were not found", name(path))
+            }
+            Error::Encode(path, e) => write!(f, "cannot write {}: {e}", name(path)),
+            Error::Prompt(e) => write!(f, "prompt failed: {e}"),
+            Error::Terminal(e) => write!(f, "terminal error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) | Error::Terminal(e) => Some(e),
+            Error::Decode(_, e) => Some(e),
+            Error::Device(e) => Some(e),
+            Error::TagRead(_, e) | Error::TagWrite(_, e) => Some(e),
+            Error::ConfigRead(_, e) => Some(e),
+            Error::ConfigWrite(_, e) => Some(e),
+            Error::Encode(_, e) => Some(e),
+            Error::Prompt(e) => Some(e),
+            Error::NoDevice
+            | Error::DeviceNotFound(_)
+            | Error::UnsupportedFormat(_)
+            | Error::EmptyPlaylist(_)
+            | Error::MissingEntries(_, _) => None,
+        }
+    }
+}
+
+impl From<inquire::InquireError> for Error {
+    fn from(e: inquire::InquireError) -> Self {
+        Error::Prompt(e)
+    }
+}
+
+impl Error {
+    #[doc = "Errors that come from the terminal itself, the TUI cannot keep running after them"]
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Terminal(_))
+    }
+}
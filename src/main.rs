@@ -1,5 +1,9 @@
 mod audio;
+mod bookmarks;
 mod cli;
+mod config;
+mod error;
+mod playlist;
 mod tui;
 
 use clap::Parser;
@@ -8,6 +12,9 @@ use tui::App;
 
 fn main() {
     let args = Cli::parse();
-    App::from(args.clone()).run();
+    if let Err(e) = App::from(args.clone()).and_then(App::run) {
+        eprintln!("[x] {e}");
+        std::process::exit(1);
+    }
     std::process::exit(0);
 }
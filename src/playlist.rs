@@ -0,0 +1,505 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+fn format(path: &Path) -> Option<Format> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "m3u" | "m3u8" => Some(Format::M3u),
+        "pls" => Some(Format::Pls),
+        "xspf" => Some(Format::Xspf),
+        _ => None,
+    }
+}
+
+pub fn is_playlist(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+#[doc = "True for .m3u files, which predate .m3u8 and are not UTF-8 unless they happen to be"]
+fn is_legacy_m3u(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("m3u"))
+}
+
+#[doc = "Tracks of a playlist file, the entries that could not be found are kept apart as written in the file"]
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    pub tracks: Vec<PathBuf>,
+    pub missing: Vec<String>,
+}
+
+impl Playlist {
+    #[doc = "Read an M3U/M3U8, PLS or XSPF playlist, relative entries are resolved against its directory"]
+    pub fn load(path: &Path) -> Result<Self> {
+        let format = format(path).ok_or(Error::UnsupportedFormat(path.to_path_buf()))?;
+        let bytes = std::fs::read(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            // Legacy .m3u files are Latin-1, whose bytes are the first 256 code points
+            Err(e) if is_legacy_m3u(path) => e.into_bytes().into_iter().map(char::from).collect(),
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        let content = content.trim_start_matches('\u{feff}');
+        let entries = match format {
+            Format::M3u => parse_m3u(content),
+            Format::Pls => parse_pls(content),
+            Format::Xspf => parse_xspf(content),
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut playlist = Self::default();
+        for entry in entries {
+            match resolve(dir, &entry) {
+                Some(track) => playlist.tracks.push(track),
+                None => playlist.missing.push(entry),
+            }
+        }
+        Ok(playlist)
+    }
+}
+
+fn parse_m3u(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[doc = "`FileN=` entries of a PLS playlist ordered by N"]
+fn parse_pls(content: &str) -> Vec<String> {
+    let mut entries: Vec<(u32, String)> = content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let number = key
+                .trim()
+                .to_lowercase()
+                .strip_prefix("file")?
+                .parse()
+                .ok()?;
+            Some((number, value.trim().to_string()))
+        })
+        .collect();
+    entries.sort_by_key(|(number, _)| *number);
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+#[doc = "First `location` of every track of an XSPF playlist, the playlist has a `location` of its own outside of the track list"]
+fn parse_xspf(content: &str) -> Vec<String> {
+    let track_list = content
+        .split_once("<trackList")
+        .map_or("", |(_, track_list)| track_list);
+    elements(track_list, "track")
+        .into_iter()
+        .filter_map(|track| elements(track, "location").into_iter().next())
+        .map(|location| xml_text(location).trim().to_string())
+        // Locations are URIs, relative ones are percent-encoded like file:// URLs
+        .map(|location| match location.contains("://") {
+            true => location,
+            false => percent_decode(&location),
+        })
+        .collect()
+}
+
+#[doc = "Raw contents of every `<name>` element of `content`, whatever its attributes, an empty element has empty contents"]
+fn elements<'a>(content: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{name}"), format!("</{name}>"));
+    let mut found = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        // <tracks> or <trackList> only share the start of the name
+        if !rest.starts_with(['>', '/']) && !rest.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let empty = rest[..end].ends_with('/');
+        rest = &rest[end + 1..];
+        if empty {
+            found.push("");
+            continue;
+        }
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+#[doc = "Text of the raw contents of an element, CDATA sections are kept as they are and entities are decoded everywhere else"]
+fn xml_text(raw: &str) -> String {
+    let mut text = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find("<![CDATA[") {
+        text.push_str(&unescape_xml(&rest[..start]));
+        rest = &rest[start + "<![CDATA[".len()..];
+        let end = rest.find("]]>").unwrap_or(rest.len());
+        text.push_str(&rest[..end]);
+        rest = rest.get(end + "]]>".len()..).unwrap_or_default();
+    }
+    text.push_str(&unescape_xml(rest));
+    text
+}
+
+#[doc = "Local file an entry points to, None when it does not exist or is not a local file"]
+fn resolve(dir: &Path, entry: &str) -> Option<PathBuf> {
+    let path = match entry.split_once("://") {
+        Some(("file", rest)) => {
+            // file://host/path keeps a host part before the path, it is empty for local files
+            let path = rest.find('/').map_or(rest, |start| &rest[start..]);
+            PathBuf::from(percent_decode(path))
+        }
+        Some(_) => return None,
+        None => PathBuf::from(entry),
+    };
+    let path = if path.is_relative() {
+        dir.join(path)
+    } else {
+        path
+    };
+    path.is_file().then_some(path)
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = (bytes[index] == b'%')
+            .then(|| input.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[doc = "Decode the predefined entities and the character references of XML in one pass, so that `&amp;lt;` stays `&lt;`"]
+fn unescape_xml(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "amp" => '&',
+                reference => {
+                    let code = reference.strip_prefix('#')?;
+                    let code = match code.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => code.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((character, end))
+        });
+        match decoded {
+            Some((character, end)) => {
+                output.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[doc = "Track written to a playlist with what its tags tell about it"]
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl Entry {
+    #[doc = "`Artist - Title` shown by players, the file name when the tags have no title"]
+    fn display_title(&self) -> String {
+        let title = self.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+        match &self.artist {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title,
+        }
+    }
+}
+
+#[doc = "Write `entries` as an extended M3U8 playlist, or as XSPF when `path` ends with .xspf"]
+pub fn save(path: &Path, entries: &[Entry]) -> Result<()> {
+    let content = match format(path) {
+        Some(Format::M3u) => write_m3u(path, entries),
+        Some(Format::Xspf) => write_xspf(entries),
+        _ => return Err(Error::UnsupportedFormat(path.to_path_buf())),
+    };
+    std::fs::write(path, content).map_err(|e| Error::Io(path.to_path_buf(), e))
+}
+
+#[doc = "Tracks under the directory of the playlist are written relative to it so that the folder can be moved"]
+fn write_m3u(path: &Path, entries: &[Entry]) -> String {
+    let dir = path
+        .parent()
+        .and_then(|dir| std::path::absolute(dir).ok())
+        .unwrap_or_default();
+    let mut content = String::from("#EXTM3U\n");
+    for entry in entries {
+        let track = std::path::absolute(&entry.path).unwrap_or_else(|_| entry.path.clone());
+        let seconds = entry
+            .duration
+            .map_or(-1, |duration| duration.as_secs_f64().round() as i64);
+        let _ = writeln!(content, "#EXTINF:{seconds},{}", entry.display_title());
+        let _ = writeln!(
+            content,
+            "{}",
+            track.strip_prefix(&dir).unwrap_or(&track).to_string_lossy()
+        );
+    }
+    content
+}
+
+fn write_xspf(entries: &[Entry]) -> String {
+    let mut content = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in entries {
+        let track = std::path::absolute(&entry.path).unwrap_or_else(|_| entry.path.clone());
+        let location = format!("file://{}", percent_encode(&track.to_string_lossy()));
+        content.push_str("    <track>\n");
+        let _ = writeln!(
+            content,
+            "      <location>{}</location>",
+            escape_xml(&location)
+        );
+        if let Some(title) = &entry.title {
+            let _ = writeln!(content, "      <title>{}</title>", escape_xml(title));
+        }
+        if let Some(artist) = &entry.artist {
+            let _ = writeln!(content, "      <creator>{}</creator>", escape_xml(artist));
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(
+                content,
+                "      <duration>{}</duration>",
+                duration.as_millis()
+            );
+        }
+        content.push_str("    </track>\n");
+    }
+    content.push_str("  </trackList>\n</playlist>\n");
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[doc = "Fresh directory under the system temporary directory holding empty `files`"]
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("playlist-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for file in files {
+            std::fs::File::create(root.join(file)).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn m3u_skips_comments_and_extinf() {
+        let entries = parse_m3u(
+            "#EXTM3U\n#EXTINF:123,Band - Song\nsong.mp3\n\n  # comment\n  other.flac  \r\n",
+        );
+        assert_eq!(entries, ["song.mp3", "other.flac"]);
+    }
+
+    #[test]
+    fn pls_orders_entries_by_number_and_skips_gaps() {
+        let entries = parse_pls(
+            "[playlist]\nNumberOfEntries=3\nFile10=ten.mp3\nTitle10=Ten\nfile2 = two.mp3\nFile1=one.mp3\nVersion=2\n",
+        );
+        assert_eq!(entries, ["one.mp3", "two.mp3", "ten.mp3"]);
+    }
+
+    #[test]
+    fn xspf_reads_locations_with_attributes_cdata_and_entities() {
+        let entries = parse_xspf(
+            "<?xml version=\"1.0\"?>\n<playlist version=\"1\">\n\
+             <location>http://example.com/playlist.xspf</location>\n\
+             <trackList>\n\
+             <track><location>file:///music/Rock%20&amp;%20Roll.mp3</location></track>\n\
+             <track><title>A &lt;b&gt;</title><location xml:base=\"x\"><![CDATA[dir/a&b.mp3]]></location></track>\n\
+             <track>\n  <location>\n    Caf%C3%A9 &#38; &#x26;amp;.mp3\n  </location>\n  <location>second.mp3</location>\n</track>\n\
+             <track><location/></track>\n\
+             </trackList>\n</playlist>\n",
+        );
+        assert_eq!(
+            entries,
+            [
+                "file:///music/Rock%20&%20Roll.mp3",
+                "dir/a&b.mp3",
+                "Café & &amp;.mp3",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_entities_are_decoded_once() {
+        assert_eq!(
+            unescape_xml("&amp;lt; &lt;&#65;&#x42;&gt; & &bogus;"),
+            "&lt; <AB> & &bogus;"
+        );
+        assert_eq!(
+            unescape_xml(&escape_xml("<a href=\"x\">'&'</a>")),
+            "<a href=\"x\">'&'</a>"
+        );
+    }
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        for path in [
+            "/music/Café del Mar/01 - Track #1 (50%).flac",
+            "a+b&c=d?.mp3",
+            "",
+        ] {
+            let encoded = percent_encode(path);
+            assert!(!encoded.contains([' ', '#', '?', '&', '+', '=']));
+            assert_eq!(percent_decode(&encoded), path);
+        }
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn entries_resolve_relative_absolute_and_file_uris() {
+        let dir = tree("resolve", &["song.mp3", "with space.mp3"]);
+        let song = dir.join("song.mp3");
+        let spaced = dir.join("with space.mp3");
+        let uri = |host: &str, path: &Path| {
+            format!("file://{host}{}", percent_encode(&path.to_string_lossy()))
+        };
+        assert_eq!(resolve(&dir, "song.mp3"), Some(song.clone()));
+        assert_eq!(
+            resolve(Path::new("/"), &song.to_string_lossy()),
+            Some(song.clone())
+        );
+        assert_eq!(
+            resolve(Path::new("/"), &uri("", &spaced)),
+            Some(spaced.clone())
+        );
+        assert_eq!(
+            resolve(Path::new("/"), &uri("localhost", &song)),
+            Some(song)
+        );
+        assert_eq!(resolve(&dir, "missing.mp3"), None);
+        assert_eq!(resolve(&dir, "http://example.com/song.mp3"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn legacy_m3u_is_read_as_latin_1() {
+        let dir = tree("latin1", &["café.mp3", "naïve.mp3"]);
+        std::fs::write(
+            dir.join("old.m3u"),
+            b"#EXTM3U\ncaf\xe9.mp3\nna\xefve.mp3\nmissing.mp3\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("new.m3u8"), "\u{feff}café.mp3\n").unwrap();
+        let old = Playlist::load(&dir.join("old.m3u")).unwrap();
+        assert_eq!(old.tracks, [dir.join("café.mp3"), dir.join("naïve.mp3")]);
+        assert_eq!(old.missing, ["missing.mp3"]);
+        let new = Playlist::load(&dir.join("new.m3u8")).unwrap();
+        assert_eq!(new.tracks, [dir.join("café.mp3")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saved_playlists_load_back() {
+        let dir = tree("save", &["a & b.mp3", "Ünïcode #1.mp3"]);
+        let entries: Vec<Entry> = ["a & b.mp3", "Ünïcode #1.mp3"]
+            .into_iter()
+            .map(|name| Entry {
+                path: dir.join(name),
+                title: Some(format!("<{name}>")),
+                duration: Some(Duration::from_secs(61)),
+                ..Entry::default()
+            })
+            .collect();
+        for name in ["saved.m3u8", "saved.xspf"] {
+            save(&dir.join(name), &entries).unwrap();
+            let playlist = Playlist::load(&dir.join(name)).unwrap();
+            assert_eq!(
+                playlist.tracks,
+                [dir.join("a & b.mp3"), dir.join("Ünïcode #1.mp3")]
+            );
+            assert!(playlist.missing.is_empty());
+        }
+        let m3u = std::fs::read_to_string(dir.join("saved.m3u8")).unwrap();
+        assert!(m3u.starts_with("#EXTM3U\n#EXTINF:61,<a & b.mp3>\na & b.mp3\n"));
+        assert!(matches!(
+            save(&dir.join("saved.pls"), &entries),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,38 +1,143 @@
+mod explorer;
+mod meter;
+mod spectrum;
+mod terminal;
 mod tui_input;
 mod utils;
-use crate::audio::{AudioPlayer, AudioSource};
+mod waveform;
+use crate::audio::{
+    AudioPlayer, AudioSource, CueTrack, DeviceSelector, EqSettings, FilterSettings, MAX_GAIN,
+    OutputDevice, PendingWaveform, RenderOptions, Repeat, ReplayGainMode, Shuffle,
+    expand_cue_sheets, is_cue_sheet, output_devices, read_cue_sheet, verify_audio_file,
+};
+use crate::bookmarks::{Bookmark, Bookmarks};
 use crate::cli::Cli;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::playlist::is_playlist;
+use explorer::FileExplorer;
 use lofty::tag::Accessor;
+use meter::LevelMeters;
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, BorderType, LineGauge, Paragraph};
-use ratatui_explorer::Theme;
+use ratatui::widgets::{
+    Bar, BarChart, BarGroup, Block, BorderType, Clear, LineGauge, List, ListItem, ListState,
+    Paragraph,
+};
+use spectrum::Spectrum;
 use std::fmt::Debug;
-use std::path::PathBuf;
-use std::time::Duration;
-use utils::{get_sample_rate, verify_path_extension};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use terminal::TerminalGuard;
+use utils::{format_timestamp, get_sample_rate};
+use waveform::WaveformView;
 
 #[derive(Default)]
 pub struct App {
     args: Option<Cli>,
+    config: Config,
     audio: Option<AudioSource>,
     state_play: Option<AppStatePlay>,
     state_player: Option<AppStatePlayer>,
 }
 
 impl App {
-    pub fn from(cli: Cli) -> Self {
-        let mut app = Self::default();
+    pub fn from(cli: Cli) -> Result<Self> {
+        let mut app = Self {
+            config: Config::load()?,
+            ..Self::default()
+        };
         match cli.get_command() {
             crate::cli::Command::Play {
                 path,
-                low_pass: _,
-                high_pass: _,
+                low_pass,
+                high_pass,
+                crossfade,
+                crossfade_curve,
+                device,
+                eq,
+                replay_gain,
+                preamp,
+                tape_speed,
             } => {
-                app.add_audio(path, cli.get_debug());
+                app.config
+                    .set_filters(low_pass, high_pass)
+                    .set_crossfade(crossfade, crossfade_curve)
+                    .set_device(device)
+                    .set_equalizer_preset(eq)
+                    .set_replay_gain(replay_gain, preamp)
+                    .set_tape_speed(tape_speed);
+                app.add_audio(path, cli.get_debug())?;
                 app.state_play = Some(AppStatePlay::default());
             }
-            crate::cli::Command::Player { cwd } => {
-                app.state_player = Some(AppStatePlayer::from(cwd, cli.get_debug()));
+            crate::cli::Command::Player {
+                cwd,
+                low_pass,
+                high_pass,
+                crossfade,
+                crossfade_curve,
+                device,
+                eq,
+                replay_gain,
+                preamp,
+                tape_speed,
+            } => {
+                app.config
+                    .set_filters(low_pass, high_pass)
+                    .set_crossfade(crossfade, crossfade_curve)
+                    .set_device(device)
+                    .set_equalizer_preset(eq)
+                    .set_replay_gain(replay_gain, preamp)
+                    .set_tape_speed(tape_speed);
+                app.state_player = Some(AppStatePlayer::from(
+                    cwd,
+                    app.config.clone(),
+                    cli.get_debug(),
+                )?);
+            }
+            crate::cli::Command::Devices => crate::audio::print_devices(),
+            crate::cli::Command::Render {
+                path,
+                output,
+                low_pass,
+                high_pass,
+                eq,
+                replay_gain,
+                preamp,
+                tape_speed,
+                speed,
+                pitch,
+                volume,
+            } => {
+                app.config
+                    .set_filters(low_pass, high_pass)
+                    .set_equalizer_preset(eq)
+                    .set_replay_gain(replay_gain, preamp)
+                    .set_tape_speed(tape_speed);
+                let path = verify_audio_file(&path).ok_or(Error::UnsupportedFormat(path))?;
+                let samples = crate::audio::render_to_wav(
+                    &path,
+                    &output,
+                    RenderOptions {
+                        low_pass: app.config.low_pass,
+                        high_pass: app.config.high_pass,
+                        equalizer: app.config.equalizer.clone(),
+                        replay_gain: app.config.replay_gain,
+                        speed,
+                        semitones: pitch,
+                        preserve_pitch: app.config.preserve_pitch,
+                        volume,
+                    },
+                )?;
+                cli.get_debug().then(|| {
+                    println!(
+                        "[?] Rendered {samples} samples of {} to {}",
+                        path.to_string_lossy(),
+                        output.to_string_lossy()
+                    )
+                });
+            }
+            crate::cli::Command::ScanLoudness { path, album, write } => {
+                utils::scan_loudness(&cli, path, album, write)?;
             }
             crate::cli::Command::TagWritter {
                 title,
@@ -41,46 +146,58 @@ impl App {
                 genre,
                 path,
             } => {
-                utils::tag_writter(&cli, title, artist, album, genre, path);
+                utils::tag_writter(&cli, title, artist, album, genre, path)?;
             }
         }
         app.args = Some(cli);
-        app
+        Ok(app)
     }
-    pub fn run(self) {
-        let cli = self.args.expect("[x] Could not get CLI arguments");
+    pub fn run(self) -> Result<()> {
+        let Some(cli) = self.args else {
+            return Ok(());
+        };
         match cli.clone().get_command() {
             crate::cli::Command::Play {
                 path,
-                low_pass,
-                high_pass,
+                low_pass: _,
+                high_pass: _,
+                crossfade: _,
+                crossfade_curve: _,
+                device: _,
+                eq: _,
+                replay_gain: _,
+                preamp: _,
+                tape_speed: _,
             } => {
-                let tag = utils::get_tags(&path);
-                if let Some(mut audio) = self.audio {
-                    match audio.play(low_pass, high_pass, cli.get_debug()) {
-                        Ok(mut player) => {
-                            self.state_play
-                                .expect("[x] Could not get app state")
-                                .set_color(Color::White)
-                                .set_full_title(audio.get_title())
-                                .set_filename(tag.title().unwrap_or_default())
-                                .set_artist(tag.artist().unwrap_or_default())
-                                .set_album(tag.album().unwrap_or_default())
-                                .set_genre(tag.genre().unwrap_or_default())
-                                .set_sample_rate(get_sample_rate(&path))
-                                .set_total_duration(utils::get_total_duration(&path))
-                                .set_debug(cli.get_debug())
-                                .run(&mut player);
-                        }
-                        Err(e) => eprintln!("{e}"),
-                    }
+                if let (Some(mut audio), Some(mut state_play)) = (self.audio, self.state_play) {
+                    let mut player = audio.play(
+                        self.config.device.as_deref().map(DeviceSelector::Query),
+                        self.config.low_pass,
+                        self.config.high_pass,
+                        cli.get_debug(),
+                    )?;
+                    player
+                        .set_crossfade(self.config.get_crossfade())
+                        .set_equalizer(self.config.equalizer.clone())
+                        .set_replay_gain(self.config.replay_gain)
+                        .set_auto_limiter(self.config.auto_limiter)
+                        .set_preserve_pitch(self.config.preserve_pitch);
+                    let track = player.current_track().cloned().unwrap_or(path);
+                    state_play
+                        .set_color(Color::White)
+                        .set_track(&track)
+                        .set_debug(cli.get_debug())
+                        .run(&mut player, &self.config)?;
                 }
             }
-            crate::cli::Command::Player { cwd: _ } => {
-                self.state_player
-                    .expect("[x] App: Expected state_player")
-                    .run(cli.get_debug());
+            crate::cli::Command::Player { .. } => {
+                if let Some(mut state_player) = self.state_player {
+                    state_player.run(cli.get_debug())?;
+                }
             }
+            crate::cli::Command::Devices
+            | crate::cli::Command::Render { .. }
+            | crate::cli::Command::ScanLoudness { .. } => {}
             crate::cli::Command::TagWritter {
                 path: _,
                 title: _,
@@ -89,18 +206,37 @@ impl App {
                 genre: _,
             } => {}
         }
+        Ok(())
     }
-    #[doc = "Check if the provided path extension is an audio file and add it to Self"]
-    fn add_audio(&mut self, path: PathBuf, debug: bool) {
-        let valid_path = verify_path_extension(&path).expect("[x]Invalid File Extension provided");
+    #[doc = "Check if the provided path extension is an audio file, a CUE sheet or a playlist and add it to Self"]
+    fn add_audio(&mut self, path: PathBuf, debug: bool) -> Result<()> {
+        if is_cue_sheet(&path) {
+            let tracks = read_cue_sheet(&path)?;
+            if tracks.is_empty() {
+                return Err(Error::EmptyPlaylist(path));
+            }
+            debug.then(|| println!("[?] CUE sheet of {} tracks transmitted", tracks.len()));
+            self.audio = Some(AudioSource::from_tracks(tracks));
+            return Ok(());
+        }
+        if is_playlist(&path) {
+            let tracks = utils::load_playlist(&path)?;
+            debug.then(|| println!("[?] Playlist of {} tracks transmitted", tracks.len()));
+            self.audio = Some(AudioSource::from_tracks(tracks));
+            return Ok(());
+        }
+        let valid_path = verify_audio_file(&path).ok_or(Error::UnsupportedFormat(path.clone()))?;
         debug.then(|| println!("Path transmitted : {valid_path:?}"));
-        self.audio = Some(AudioSource::from(path));
+        // A file described by a CUE sheet plays as its tracks
+        self.audio = Some(AudioSource::from_tracks(expand_cue_sheets(vec![path])));
+        Ok(())
     }
 }
 
 #[derive(Debug, Default, Clone)]
 struct AppStatePlay {
     running: bool,
+    track: PathBuf,
     full_title: String,
     file_name: String,
     current_duration: Duration,
@@ -109,8 +245,37 @@ struct AppStatePlay {
     album: String,
     genre: String,
     sample_rate: String,
+    #[doc = "ReplayGain mode and the gain it applies to the current track"]
+    replay_gain: String,
+    #[doc = "A-B loop points of the current track"]
+    ab_loop: String,
+    #[doc = "Speed and pitch shift of the player"]
+    playback: String,
+    repeat: Repeat,
+    shuffle: Shuffle,
     debug: bool,
     color: Color,
+    #[doc = "Content of the go to time prompt while it is open"]
+    goto: Option<String>,
+    device_picker: Option<DevicePicker>,
+    eq_panel: Option<EqPanel>,
+    filter_panel: Option<FilterPanel>,
+    #[doc = "Position and name typed so far of the bookmark being added"]
+    bookmark_prompt: Option<(Duration, String)>,
+    bookmark_list: Option<BookmarkList>,
+    #[doc = "Whether the list of every key is shown, toggled with <?>"]
+    help: bool,
+    spectrum: Spectrum,
+    meters: LevelMeters,
+    #[doc = "Overview of the current track, drawn instead of the gauge once computed"]
+    waveform: PendingWaveform,
+    #[doc = "CUE track played in the file, the rest of the fields then describe it"]
+    cue: Option<CueTrack>,
+    file_duration: Duration,
+    #[doc = "Fractions of the file the CUE track spans, None for the whole file"]
+    range: Option<(f64, f64)>,
+    #[doc = "Message of the last error, shown in a popup until a key is pressed"]
+    error: Option<String>,
 }
 
 impl Widget for AppStatePlay {
@@ -129,91 +294,588 @@ impl Widget for AppStatePlay {
             .style(Style::default().fg(self.color))
             .render(outer_area, buf);
 
-        let layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(80), Constraint::Percentage(20)])
-            .split(inner_area.inner(Margin {
-                horizontal: 5,
-                vertical: 5,
-            }));
+        let layout = Self::layout(area);
 
         Block::bordered()
             .style(Style::default().fg(Color::Blue))
             .title(format!(
-                "{} - {:02}:{:02}/{:02}:{:02}",
+                "{} - {:02}:{:02}/{:02}:{:02} - Repeat: {} | Shuffle: {}",
                 self.full_title,
                 (self.current_duration.as_secs() - (self.current_duration.as_secs() % 60)) / 60,
                 self.current_duration.as_secs() % 60,
                 (self.total_duration.as_secs() - (self.total_duration.as_secs() % 60)) / 60,
                 self.total_duration.as_secs() % 60,
+                self.repeat.name(),
+                self.shuffle.name(),
             ))
-            .title_bottom("[Volume Up ▲ | Volume Down ▼ | Fast Forward ▶ | Rewind ◀ | Slow Down <Shift> + ▼ | Speed Up <Shift> + ▲]")
+            .title_bottom(
+                "[Pause <Enter> | Volume ▲▼ | Seek ◀ ▶ | Next <n> | Previous <p> | Keys <?>]",
+            )
             .render(inner_area, buf);
 
+        let [content_area, meters_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(4)]).areas(layout[0]);
+        let [text_area, spectrum_area] = if self.spectrum.visible {
+            Layout::vertical([Constraint::Length(10), Constraint::Min(0)]).areas(content_area)
+        } else {
+            [content_area, Rect::default()]
+        };
         Paragraph::new(format!(
-            "Title: {}\nArtist: {}\nAlbum: {}\nGenre: {}\nSample Rate: {}\nTotal Duration: {:02}:{:02}",
-            self.file_name.is_empty().then(|| "<None>").or(Some(&self.file_name)).unwrap(),
-            self.artist
-                .is_empty()
-                .then(|| "<None>")
-                .or(Some(&self.artist))
-                .unwrap(),
-            self.album
-                .is_empty()
-                .then(|| "<None>")
-                .or(Some(&self.album))
-                .unwrap(),
-            self.genre
-                .is_empty()
-                .then(|| "<None>")
-                .or(Some(&self.genre))
-                .unwrap(),
-            self.sample_rate
-                .is_empty()
-                .then(|| "<None>")
-                .or(Some(&self.sample_rate))
-                .unwrap(),
+            "Title: {}\nArtist: {}\nAlbum: {}\nGenre: {}\nSample Rate: {}\nReplay Gain: {}\nLoop: {}\nSpeed: {}\nTotal Duration: {:02}:{:02}",
+            or_none(&self.file_name),
+            or_none(&self.artist),
+            or_none(&self.album),
+            or_none(&self.genre),
+            or_none(&self.sample_rate),
+            or_none(&self.replay_gain),
+            or_none(&self.ab_loop),
+            or_none(&self.playback),
             (self.total_duration.as_secs() - (self.total_duration.as_secs() % 60)) / 60,
             self.total_duration.as_secs() % 60,
         ))
         .style(Style::default().fg(Color::Yellow))
         .centered()
-        .render(layout[0], buf);
-
-        LineGauge::default()
-            .style(Style::default().fg(Color::Yellow))
-            .line_set(symbols::line::THICK)
-            .filled_style(Style::default().fg(Color::Yellow))
-            .unfilled_style(Style::default().fg(Color::Black))
-            .ratio(
-                (self.current_duration.as_secs_f64() / self.total_duration.as_secs_f64())
-                    .clamp(0., 1.),
+        .render(text_area, buf);
+        self.spectrum
+            .visible
+            .then(|| self.spectrum.render(spectrum_area, buf));
+        self.meters.render(meters_area, buf);
+
+        let position =
+            (self.current_duration.as_secs_f64() / self.total_duration.as_secs_f64()).clamp(0., 1.);
+        match self.waveform.get() {
+            Some(waveform) => WaveformView {
+                waveform,
+                position,
+                range: self.range.unwrap_or((0., 1.)),
+            }
+            .render(layout[1], buf),
+            None => LineGauge::default()
+                .style(Style::default().fg(Color::Yellow))
+                .line_set(symbols::line::THICK)
+                .filled_style(Style::default().fg(Color::Yellow))
+                .unfilled_style(Style::default().fg(Color::Black))
+                .ratio(position)
+                .render(layout[1], buf),
+        }
+
+        if let Some(input) = &self.goto {
+            let popup = centered_rect(area, 30, 3);
+            Clear.render(popup, buf);
+            Paragraph::new(input.as_str())
+                .block(
+                    Block::bordered()
+                        .title("[Go to mm:ss]")
+                        .border_type(BorderType::Rounded),
+                )
+                .style(Style::default().fg(Color::Yellow))
+                .render(popup, buf);
+        }
+        if let Some(picker) = self.device_picker {
+            picker.render(area, buf);
+        }
+        if let Some(panel) = self.eq_panel {
+            panel.render(area, buf);
+        }
+        if let Some(panel) = self.filter_panel {
+            panel.render(area, buf);
+        }
+        if let Some((_, name)) = &self.bookmark_prompt {
+            let popup = centered_rect(area, 40, 3);
+            Clear.render(popup, buf);
+            Paragraph::new(name.as_str())
+                .block(
+                    Block::bordered()
+                        .title("[Bookmark Name]")
+                        .title_bottom("[Save <Enter> | Cancel <Esc>]")
+                        .border_type(BorderType::Rounded),
+                )
+                .style(Style::default().fg(Color::Yellow))
+                .render(popup, buf);
+        }
+        if let Some(list) = self.bookmark_list {
+            list.render(area, buf);
+        }
+        if self.help {
+            render_help(area, buf);
+        }
+        if let Some(error) = &self.error {
+            render_error(area, buf, error);
+        }
+    }
+}
+
+#[doc = "Popup editing the equalizer bands, opened with <e>"]
+#[derive(Debug, Clone, Default)]
+struct EqPanel {
+    settings: EqSettings,
+    selected: usize,
+}
+
+impl Widget for EqPanel {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let bars: Vec<Bar> = self
+            .settings
+            .bands
+            .iter()
+            .enumerate()
+            .map(|(index, band)| {
+                let label = if band.frequency >= 1000. {
+                    format!("{}k", band.frequency / 1000.)
+                } else {
+                    format!("{}", band.frequency)
+                };
+                Bar::default()
+                    .value((band.gain + MAX_GAIN).round() as u64)
+                    .text_value(format!("{:+}", band.gain))
+                    .label(Line::from(label))
+                    .style(Style::default().fg(if index == self.selected {
+                        Color::Yellow
+                    } else {
+                        Color::Blue
+                    }))
+            })
+            .collect();
+        let title = match (self.settings.enabled, self.settings.preset()) {
+            (false, _) => "[Equalizer - Off]".to_string(),
+            (true, Some(preset)) => format!("[Equalizer - {}]", preset.name()),
+            (true, None) => "[Equalizer - Custom]".to_string(),
+        };
+        let popup = centered_rect(
+            area,
+            area.width
+                .min(bars.len() as u16 * 6 + 2)
+                .max(62.min(area.width)),
+            area.height.min(16),
+        );
+        Clear.render(popup, buf);
+        BarChart::default()
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .title_bottom("[Band ◀ ▶ | Gain ▲▼ | Preset <p> | On/Off <Enter> | Close <e>]")
+                    .border_type(BorderType::Rounded),
+            )
+            .data(BarGroup::default().bars(&bars))
+            .max(2 * MAX_GAIN as u64)
+            .bar_width(5)
+            .bar_gap(1)
+            .render(popup, buf);
+    }
+}
+
+#[doc = "Popup toggling the low-pass and high-pass filters and moving their cutoff, opened with <f>"]
+#[derive(Debug, Clone, Default)]
+struct FilterPanel {
+    settings: FilterSettings,
+    #[doc = "0 for the low-pass row, 1 for the high-pass one"]
+    selected: usize,
+}
+
+impl Widget for FilterPanel {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows = [
+            ("Low-pass", self.settings.low_pass),
+            ("High-pass", self.settings.high_pass),
+        ];
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|(name, filter)| {
+                let item = ListItem::new(format!(
+                    "{name:<10} {:<5} {:>5} Hz",
+                    if filter.enabled { "[on]" } else { "[off]" },
+                    filter.frequency
+                ));
+                if filter.enabled {
+                    item.style(Style::default().fg(Color::Yellow))
+                } else {
+                    item
+                }
+            })
+            .collect();
+        let popup = centered_rect(area, area.width.min(62), area.height.min(4));
+        Clear.render(popup, buf);
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("[Filters]")
+                    .title_bottom(
+                        "[Cutoff ◀ ▶ | Octave <Shift> + ◀ ▶ | On/Off <Enter> | Close <f>]",
+                    )
+                    .border_type(BorderType::Rounded),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        StatefulWidget::render(
+            list,
+            popup,
+            buf,
+            &mut ListState::default().with_selected(Some(self.selected)),
+        );
+    }
+}
+
+#[doc = "Popup listing the output devices, opened with <o>"]
+#[derive(Debug, Clone, Default)]
+struct DevicePicker {
+    devices: Vec<OutputDevice>,
+    current: String,
+    state: ListState,
+}
+
+impl DevicePicker {
+    fn open(current: &str) -> Self {
+        let devices = output_devices();
+        let selected = devices
+            .iter()
+            .position(|device| device.name == current)
+            .or((!devices.is_empty()).then_some(0));
+        Self {
+            devices,
+            current: current.to_string(),
+            state: ListState::default().with_selected(selected),
+        }
+    }
+}
+
+impl Widget for DevicePicker {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let items: Vec<ListItem> = self
+            .devices
+            .iter()
+            .map(|device| {
+                if device.name == self.current {
+                    ListItem::new(format!("▶ {}", device.label()))
+                        .style(Style::default().fg(Color::Yellow))
+                } else {
+                    ListItem::new(format!("  {}", device.label()))
+                }
+            })
+            .collect();
+        let popup = centered_rect(
+            area,
+            area.width.min(70),
+            area.height.min(self.devices.len() as u16 + 2),
+        );
+        Clear.render(popup, buf);
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("[Output Device]")
+                    .title_bottom("[Switch <Enter> | Close <Esc>]")
+                    .border_type(BorderType::Rounded),
             )
-            .render(layout[1], buf);
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        StatefulWidget::render(list, popup, buf, &mut self.state);
     }
 }
 
+#[doc = "Prompt naming the playlist the queue is saved as, opened with <w> in the queue"]
+#[derive(Debug, Clone, Default)]
+struct PlaylistPrompt {
+    name: String,
+    #[doc = "Set once Enter was pressed on the name of an existing file, a second Enter overwrites it"]
+    overwrite: bool,
+}
+
+#[doc = "Popup listing the bookmarks of the current track, opened with <B>"]
+#[derive(Debug, Clone, Default)]
+struct BookmarkList {
+    track: PathBuf,
+    bookmarks: Vec<Bookmark>,
+    state: ListState,
+}
+
+impl BookmarkList {
+    fn open(track: &Path) -> Result<Self> {
+        let bookmarks = Bookmarks::load()?.of(track).to_vec();
+        let selected = (!bookmarks.is_empty()).then_some(0);
+        Ok(Self {
+            track: track.to_path_buf(),
+            bookmarks,
+            state: ListState::default().with_selected(selected),
+        })
+    }
+}
+
+impl Widget for BookmarkList {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let items: Vec<ListItem> = if self.bookmarks.is_empty() {
+            vec![ListItem::new("No bookmark, add one with <b>")]
+        } else {
+            self.bookmarks
+                .iter()
+                .map(|bookmark| {
+                    ListItem::new(format!(
+                        "{}  {}",
+                        format_timestamp(bookmark.position()),
+                        bookmark.name
+                    ))
+                })
+                .collect()
+        };
+        let popup = centered_rect(
+            area,
+            area.width.min(60),
+            area.height.min(items.len() as u16 + 2),
+        );
+        Clear.render(popup, buf);
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("[Bookmarks]")
+                    .title_bottom("[Jump <Enter> | Delete <d> | Close <Esc>]")
+                    .border_type(BorderType::Rounded),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        StatefulWidget::render(list, popup, buf, &mut self.state);
+    }
+}
+
+#[doc = "Keys of the player and what they do, listed by the <?> popup"]
+const PLAY_KEYS: [(&str, &str); 27] = [
+    ("Enter", "Play / Pause"),
+    ("▲ ▼", "Volume"),
+    ("◀ ▶", "Seek"),
+    ("Shift + ◀ ▶", "Seek far"),
+    ("Shift + ▲ ▼", "Speed"),
+    ("g", "Go to a time"),
+    ("0-9", "Jump to a tenth of the track"),
+    ("Home", "Restart"),
+    ("n", "Next track"),
+    ("p", "Previous track"),
+    ("o", "Output device"),
+    ("e", "Equalizer"),
+    ("f", "Filters"),
+    ("r", "Replay gain mode"),
+    ("R", "Repeat"),
+    ("s", "Shuffle"),
+    ("+ -", "Pitch"),
+    ("t", "Keep pitch when changing speed"),
+    ("v", "Spectrum"),
+    ("m", "Spectrum mode"),
+    ("l", "Limiter"),
+    ("k", "Reset clip indicator"),
+    ("[ ]", "Loop from A to B"),
+    ("\\", "Clear loop"),
+    ("b", "Add a bookmark"),
+    ("B", "Bookmarks"),
+    ("q", "Quit"),
+];
+
+#[doc = "Draw the list of every key of the player in a popup centered in `area`"]
+fn render_help(area: Rect, buf: &mut Buffer) {
+    let popup = centered_rect(
+        area,
+        area.width.min(50),
+        area.height.min(PLAY_KEYS.len() as u16 + 2),
+    );
+    Clear.render(popup, buf);
+    let items: Vec<ListItem> = PLAY_KEYS
+        .iter()
+        .map(|(keys, action)| ListItem::new(format!("{keys:>12}  {action}")))
+        .collect();
+    Widget::render(
+        List::new(items).block(
+            Block::bordered()
+                .title("[Keys]")
+                .title_bottom("[Close <?> | <Esc>]")
+                .border_type(BorderType::Rounded),
+        ),
+        popup,
+        buf,
+    );
+}
+
+#[doc = "Draw an error message in a popup centered in `area`"]
+fn render_error(area: Rect, buf: &mut Buffer, message: &str) {
+    let width = (message.chars().count() as u16 + 4).max(30).min(area.width);
+    let popup = centered_rect(area, width, 3);
+    Clear.render(popup, buf);
+    Paragraph::new(message)
+        .block(
+            Block::bordered()
+                .title("[Error]")
+                .title_bottom("[Close <any key>]")
+                .border_type(BorderType::Rounded),
+        )
+        .style(Style::default().fg(Color::Red))
+        .centered()
+        .render(popup, buf);
+}
+
+#[doc = "Area of `width` x `height` cells centered in `area`"]
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(layout::Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(layout::Flex::Center)
+        .areas(area);
+    area
+}
+
+fn or_none(field: &str) -> &str {
+    if field.is_empty() { "<None>" } else { field }
+}
+
 impl AppStatePlay {
-    pub fn run(&mut self, audio_player: &mut AudioPlayer) {
+    #[doc = "Areas of the metadata paragraph and of the progress gauge when rendered in `area`"]
+    fn layout(area: Rect) -> [Rect; 2] {
+        Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(80), Constraint::Percentage(20)])
+            .areas(area.inner(Margin {
+                horizontal: 6,
+                vertical: 6,
+            }))
+    }
+    pub fn gauge_area(area: Rect) -> Rect {
+        Self::layout(area)[1]
+    }
+    #[doc = "True while a popup takes the key presses"]
+    pub fn prompt_open(&self) -> bool {
+        self.goto.is_some()
+            || self.device_picker.is_some()
+            || self.eq_panel.is_some()
+            || self.filter_panel.is_some()
+            || self.bookmark_prompt.is_some()
+            || self.bookmark_list.is_some()
+            || self.help
+    }
+    pub fn run(&mut self, audio_player: &mut AudioPlayer, config: &Config) -> Result<()> {
         self.debug.then(|| println!("[?] Entering the main loop"));
         self.running = true;
         self.debug.then(|| println!("[?] AppState {self:?}"));
-        let mut term = ratatui::init();
+        let mut term = TerminalGuard::init()?;
         while self.running {
+            term.interrupted().then(|| self.stop());
+            audio_player.update();
             audio_player.is_empty().then(|| self.stop());
+            self.sync_track(audio_player);
             self.current_duration = audio_player.get_current_duration();
             term.draw(|frame| {
                 self.draw(frame);
             })
             .is_err()
             .then(|| self.stop());
-            tui_input::handle_play_event(audio_player, &mut self.running)
-                .is_err()
-                .then(|| self.stop());
+            let event = if self.error.is_some() {
+                tui_input::dismiss_error(&mut self.error, &mut self.running)
+            } else {
+                tui_input::handle_play_event(audio_player, self, config)
+            };
+            if let Err(e) = event {
+                self.show_error(e);
+            }
         }
         self.debug.then(|| println!("[?]Restoring terminal"));
-        ratatui::restore();
+        drop(term);
         self.debug.then(|| println!("[?]Exiting main loop"));
+        Ok(())
+    }
+    #[doc = "Show a recoverable error in the popup, terminal errors stop the loop instead"]
+    fn show_error(&mut self, error: Error) {
+        self.debug.then(|| println!("[x] {error}"));
+        if error.is_fatal() {
+            self.stop();
+        } else {
+            self.error = Some(error.to_string());
+        }
+    }
+    #[doc = "Fill every metadata field from the tags of the provided file, fields stay empty when they cannot be read"]
+    pub fn set_track(&mut self, path: &Path) -> &mut Self {
+        self.track = path.to_path_buf();
+        self.waveform = PendingWaveform::load(path.to_path_buf());
+        self.read_tags()
+    }
+    #[doc = "Fill the metadata fields from the tags of the current file, as they are before a CUE track overrides them"]
+    fn read_tags(&mut self) -> &mut Self {
+        let path = &self.track.clone();
+        let tag = utils::get_tags(path).ok();
+        (self.cue, self.range) = (None, None);
+        let file_duration = utils::get_total_duration(path).unwrap_or_default();
+        self.file_duration = file_duration;
+        self.set_full_title(path.to_string_lossy())
+            .set_filename(tag.as_ref().and_then(|tag| tag.title()).unwrap_or_default())
+            .set_artist(
+                tag.as_ref()
+                    .and_then(|tag| tag.artist())
+                    .unwrap_or_default(),
+            )
+            .set_album(tag.as_ref().and_then(|tag| tag.album()).unwrap_or_default())
+            .set_genre(tag.as_ref().and_then(|tag| tag.genre()).unwrap_or_default())
+            .set_sample_rate(get_sample_rate(path).unwrap_or_default())
+            .set_total_duration(file_duration)
+    }
+    #[doc = "Describe the CUE track played in the current file with its own title, performer and duration"]
+    fn set_cue(&mut self, cue: CueTrack, total_duration: Duration) -> &mut Self {
+        let file_duration = self.file_duration.as_secs_f64();
+        self.range = (file_duration > 0.).then(|| {
+            (
+                cue.start.as_secs_f64() / file_duration,
+                cue.end.map_or(1., |end| end.as_secs_f64() / file_duration),
+            )
+        });
+        self.set_full_title(format!(
+            "{} - Track {:02}",
+            self.track.to_string_lossy(),
+            cue.number
+        ));
+        if let Some(title) = &cue.title {
+            self.set_filename(title);
+        }
+        if let Some(performer) = &cue.performer {
+            self.set_artist(performer);
+        }
+        self.cue = Some(cue);
+        self.set_total_duration(total_duration)
+    }
+    #[doc = "Refresh the live readouts and reload the metadata when the player moved to another track of its queue"]
+    fn sync_track(&mut self, audio_player: &AudioPlayer) {
+        self.spectrum.update(audio_player);
+        self.meters.update(audio_player);
+        (self.repeat, self.shuffle) = (audio_player.repeat(), audio_player.shuffle());
+        // The loop points are positions in the file, shown from the start of the CUE track
+        let start = audio_player
+            .current_cue()
+            .map_or(Duration::ZERO, |cue| cue.start);
+        let relative = |position: Duration| format_timestamp(position.saturating_sub(start));
+        self.ab_loop = match audio_player.ab_loop() {
+            None => "Off".to_string(),
+            Some(ab_loop) => format!(
+                "{} → {}",
+                relative(ab_loop.a),
+                ab_loop.b.map_or("B <]>".to_string(), relative)
+            ),
+        };
+        self.playback = format!(
+            "{:.1}x ({}) | Pitch: {:+} st",
+            audio_player.speed(),
+            if audio_player.preserve_pitch() {
+                "pitch kept"
+            } else {
+                "tape"
+            },
+            audio_player.semitones()
+        );
+        let replay_gain = audio_player.replay_gain();
+        self.replay_gain = match replay_gain.mode {
+            ReplayGainMode::Off => replay_gain.mode.name().to_string(),
+            mode => format!("{} ({:+.1} dB)", mode.name(), audio_player.track_gain_db()),
+        };
+        if let Some(track) = audio_player
+            .current_track()
+            .filter(|track| **track != self.track)
+            .cloned()
+        {
+            self.set_track(&track);
+        }
+        if audio_player.current_cue() != self.cue.as_ref() {
+            // Tracks of the same file share its tags, the CUE sheet only overrides some
+            self.read_tags();
+            if let Some(cue) = audio_player.current_cue().cloned() {
+                self.set_cue(cue, audio_player.get_total_duration().unwrap_or_default());
+            }
+        }
     }
     pub fn set_color(&mut self, c: Color) -> &mut Self {
         self.color = c;
@@ -263,84 +925,124 @@ struct AppStatePlayer {
     running: bool,
     #[allow(dead_code)]
     cwd: PathBuf,
-    file_explorer: ratatui_explorer::FileExplorer,
+    file_explorer: FileExplorer,
     which: PlayerSelection,
+    config: Config,
+    queue_state: ListState,
+    #[doc = "Time and row of the last left click, used to detect double clicks"]
+    last_click: Option<(Instant, u16)>,
     audio: Option<AudioSource>,
     player: Option<AudioPlayer>,
     audio_tui: AppStatePlay,
+    #[doc = "File name typed so far while saving the queue as a playlist"]
+    playlist_prompt: Option<PlaylistPrompt>,
+    #[doc = "Message of the last error, shown in a popup until a key is pressed"]
+    error: Option<String>,
 }
 
-#[derive(Default)]
+#[derive(Default, PartialEq)]
 pub enum PlayerSelection {
     #[default]
     FileExplorer,
     AudioPlayer,
+    Queue,
 }
 
 impl PlayerSelection {
-    pub fn toggle(&mut self) -> &mut Self {
+    pub fn cycle(&mut self) -> &mut Self {
         match self {
             PlayerSelection::FileExplorer => *self = PlayerSelection::AudioPlayer,
-            PlayerSelection::AudioPlayer => *self = PlayerSelection::FileExplorer,
+            PlayerSelection::AudioPlayer => *self = PlayerSelection::Queue,
+            PlayerSelection::Queue => *self = PlayerSelection::FileExplorer,
         }
         self
     }
+    fn color(&self, target: PlayerSelection) -> Color {
+        if *self == target {
+            Color::Yellow
+        } else {
+            Color::White
+        }
+    }
 }
 
-impl Default for AppStatePlayer {
-    fn default() -> Self {
-        Self {
+impl AppStatePlayer {
+    pub fn from(cwd: Option<PathBuf>, config: Config, debug: bool) -> Result<Self> {
+        let mut default = Self {
             which: PlayerSelection::default(),
+            config,
+            queue_state: ListState::default(),
+            last_click: None,
             running: false,
             cwd: PathBuf::new(),
-            file_explorer: ratatui_explorer::FileExplorer::new()
-                .expect("[x] Ratatui_explorer: Could not create new explorer"),
+            file_explorer: FileExplorer::new()?,
             player: None,
             audio: None,
             audio_tui: AppStatePlay::default(),
-        }
-    }
-}
-
-impl AppStatePlayer {
-    pub fn from(cwd: Option<PathBuf>, debug: bool) -> Self {
-        let mut default = Self::default();
+            playlist_prompt: None,
+            error: None,
+        };
         if let Some(cwd) = cwd {
-            default.file_explorer.set_cwd(cwd.clone()).is_ok().then(|| {
-                debug.then(|| println!("[?] CWD set to {}", cwd.to_string_lossy()));
-            });
-            default
-        } else {
-            default
+            // A playlist opens its directory and starts playing
+            let (dir, playlist) = if is_playlist(&cwd) {
+                let dir = cwd
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                (dir.to_path_buf(), Some(cwd.clone()))
+            } else {
+                (cwd.clone(), None)
+            };
+            default.file_explorer.set_cwd(dir.clone())?;
+            debug.then(|| println!("[?] CWD set to {}", dir.to_string_lossy()));
+            if let Some(playlist) = playlist {
+                tui_input::play_queue(
+                    utils::load_playlist(&playlist)?,
+                    0,
+                    &mut default.audio,
+                    &mut default.player,
+                    &mut default.audio_tui,
+                    &default.config,
+                    debug,
+                )?;
+                default.which = PlayerSelection::AudioPlayer;
+            }
         }
+        Ok(default)
     }
-    pub fn run(&mut self, debug: bool) {
-        let mut term = ratatui::init();
+    pub fn run(&mut self, debug: bool) -> Result<()> {
+        let mut term = TerminalGuard::init()?;
         self.running = true;
         while self.running {
+            term.interrupted().then(|| self.stop());
             self.file_explorer
-                .set_theme(
-                    Theme::default()
-                        .add_default_title()
-                        .with_style(Style::default().fg(match self.which {
-                            PlayerSelection::FileExplorer => Color::Yellow,
-                            PlayerSelection::AudioPlayer => Color::White,
-                        })),
-                );
-            self.audio_tui.set_color(match self.which {
-                PlayerSelection::FileExplorer => Color::White,
-                PlayerSelection::AudioPlayer => Color::Yellow,
-            });
-            if let Some(player) = &self.player {
+                .set_color(self.which.color(PlayerSelection::FileExplorer))
+                .update();
+            self.audio_tui
+                .set_color(self.which.color(PlayerSelection::AudioPlayer));
+            if let Some(player) = &mut self.player {
+                player.update();
+                self.audio_tui.sync_track(player);
                 self.audio_tui.current_duration = player.get_current_duration();
+                let len = player.get_queue().tracks().len();
+                match self.queue_state.selected() {
+                    None => self.queue_state.select(player.get_queue().current_index()),
+                    Some(selected) if selected >= len => {
+                        self.queue_state.select(len.checked_sub(1))
+                    }
+                    _ => {}
+                }
                 if player.is_empty() {
                     self.audio = None;
                     self.audio_tui = AppStatePlay::default();
-                    self.which.toggle();
+                    self.queue_state = ListState::default();
+                    self.which = PlayerSelection::FileExplorer;
                 }
             }
-            if self.audio.is_none() {
-                self.player = None;
+            if self.audio.is_none()
+                && let Some(player) = self.player.take()
+            {
+                self.config.keep_player_settings(&player);
             }
             term.draw(|frame| {
                 self.draw(frame);
@@ -349,25 +1051,57 @@ impl AppStatePlayer {
             .then(|| {
                 self.stop();
             });
-            tui_input::handle_player_event(
-                &mut self.running,
-                &mut self.file_explorer,
-                &mut self.which,
-                &mut self.audio,
-                &mut self.player,
-                &mut self.audio_tui,
-                debug,
-            )
-            .is_err()
-            .then(|| self.stop());
+            let event = if self.error.is_some() {
+                tui_input::dismiss_error(&mut self.error, &mut self.running)
+            } else {
+                tui_input::handle_player_event(
+                    &mut self.running,
+                    &mut self.file_explorer,
+                    &mut self.which,
+                    &mut self.audio,
+                    &mut self.player,
+                    &mut self.audio_tui,
+                    &mut self.queue_state,
+                    &mut self.last_click,
+                    &mut self.playlist_prompt,
+                    &self.config,
+                    debug,
+                )
+            };
+            if let Err(e) = event {
+                debug.then(|| println!("[x] {e}"));
+                if e.is_fatal() {
+                    self.stop();
+                } else {
+                    self.error = Some(e.to_string());
+                }
+            }
         }
-        ratatui::restore();
+        Ok(())
     }
-    fn draw(&mut self, f: &mut Frame) {
-        let inner_area = f.area().inner(Margin {
+    #[doc = "Areas of the file explorer, the queue and the now playing widget, the last two are empty when nothing plays"]
+    fn layout(area: Rect, playing: bool) -> [Rect; 3] {
+        let inner_area = area.inner(Margin {
             horizontal: 1,
             vertical: 1,
         });
+        if !playing {
+            return [inner_area, Rect::default(), Rect::default()];
+        }
+        let [top, bottom] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(inner_area.inner(Margin {
+                horizontal: 1,
+                vertical: 1,
+            }));
+        let [explorer, queue] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(top);
+        [explorer, queue, bottom]
+    }
+    fn draw(&mut self, f: &mut Frame) {
         let outer_area = f.area();
 
         Block::bordered()
@@ -378,24 +1112,74 @@ impl AppStatePlayer {
             .style(Style::default().fg(Color::White))
             .render(outer_area, f.buffer_mut());
 
+        let [explorer, queue, play] = Self::layout(f.area(), self.audio.is_some());
+        self.file_explorer.render(explorer, f.buffer_mut());
         if self.audio.is_some() {
-            let layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .split(inner_area.inner(Margin {
-                    horizontal: 1,
-                    vertical: 1,
-                }));
-
-            self.file_explorer
-                .widget()
-                .render(layout[0], f.buffer_mut());
-            self.audio_tui.clone().render(layout[1], f.buffer_mut());
-        } else {
-            self.file_explorer
-                .widget()
-                .render(inner_area, f.buffer_mut());
+            self.render_queue(queue, f.buffer_mut());
+            self.audio_tui.clone().render(play, f.buffer_mut());
+        }
+        if let Some(prompt) = &self.playlist_prompt {
+            let popup = centered_rect(outer_area, 50, 3);
+            Clear.render(popup, f.buffer_mut());
+            let (title, bottom) = if prompt.overwrite {
+                (
+                    "[File Exists]",
+                    "[Overwrite <Enter> | Rename <Type> | Cancel <Esc>]",
+                )
+            } else {
+                (
+                    "[Save Queue As .m3u8 / .xspf]",
+                    "[Save <Enter> | Cancel <Esc>]",
+                )
+            };
+            Paragraph::new(prompt.name.as_str())
+                .block(
+                    Block::bordered()
+                        .title(title)
+                        .title_bottom(bottom)
+                        .border_type(BorderType::Rounded),
+                )
+                .style(Style::default().fg(Color::Yellow))
+                .render(popup, f.buffer_mut());
         }
+        if let Some(error) = &self.error {
+            render_error(outer_area, f.buffer_mut(), error);
+        }
+    }
+    fn render_queue(&mut self, area: Rect, buf: &mut Buffer) {
+        let Some(player) = &self.player else {
+            return;
+        };
+        let queue = player.get_queue();
+        let items: Vec<ListItem> = queue
+            .tracks()
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                let name = track.name();
+                if queue.current_index() == Some(index) {
+                    ListItem::new(format!("▶ {name}")).style(Style::default().fg(Color::Yellow))
+                } else {
+                    ListItem::new(format!("  {name}"))
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(format!(
+                        "[Queue {}/{}]",
+                        queue.current_index().map_or(0, |index| index + 1),
+                        queue.tracks().len()
+                    ))
+                    .title_bottom("[Jump <Enter> | Remove <d> | Move <Shift> + ▲▼ | Save <w>]")
+                    .style(Style::default().fg(self.which.color(PlayerSelection::Queue))),
+            )
+            .highlight_style(match self.which {
+                PlayerSelection::Queue => Style::default().add_modifier(Modifier::REVERSED),
+                _ => Style::default(),
+            });
+        StatefulWidget::render(list, area, buf, &mut self.queue_state);
     }
     fn stop(&mut self) {
         self.running = false;
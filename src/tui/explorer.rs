@@ -0,0 +1,1105 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use lofty::tag::Accessor;
+use ratatui::{
+    crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    prelude::*,
+    widgets::{Block, BorderType, Clear, List, ListItem, ListState},
+};
+
+use super::{centered_rect, utils};
+use crate::{
+    audio::{is_cue_sheet, verify_audio_file},
+    error::{Error, Result},
+    playlist::is_playlist,
+};
+
+#[doc = "Entries skipped by Page Up and Page Down"]
+const SCROLL_COUNT: usize = 12;
+
+#[doc = "Files the recursive scan finds before the search results are rebuilt, unless `RESCAN_INTERVAL` passed first"]
+const RESCAN_FILES: usize = 500;
+
+#[doc = "Longest wait before the files found by the recursive scan show in the results"]
+const RESCAN_INTERVAL: Duration = Duration::from_millis(250);
+
+#[doc = "Move through the explorer, mapped from the same keys as the vi-like bindings of most file managers"]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Up,
+    Down,
+    #[doc = "Go to the parent directory"]
+    Left,
+    #[doc = "Open the selected directory"]
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    None,
+}
+
+impl From<&Event> for Input {
+    fn from(event: &Event) -> Self {
+        let Event::Key(key) = event else {
+            return Input::None;
+        };
+        if !matches!(key.kind, KeyEventKind::Press | KeyEventKind::Repeat) {
+            return Input::None;
+        }
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => Input::Down,
+            KeyCode::Char('k') | KeyCode::Up => Input::Up,
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => Input::Left,
+            KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => Input::Right,
+            KeyCode::Home => Input::Home,
+            KeyCode::End => Input::End,
+            KeyCode::PageUp => Input::PageUp,
+            KeyCode::PageDown => Input::PageDown,
+            _ => Input::None,
+        }
+    }
+}
+
+#[doc = "Order of the files, directories always come first sorted by name"]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortBy {
+    #[default]
+    Name,
+    #[doc = "Most recently modified first"]
+    Modified,
+    Duration,
+    TrackNumber,
+}
+
+impl SortBy {
+    pub const ALL: [SortBy; 4] = [
+        SortBy::Name,
+        SortBy::Modified,
+        SortBy::Duration,
+        SortBy::TrackNumber,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortBy::Name => "Name",
+            SortBy::Modified => "Modified",
+            SortBy::Duration => "Duration",
+            SortBy::TrackNumber => "Track Number",
+        }
+    }
+}
+
+#[doc = "What the tags of an audio file tell about it"]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotation {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl Annotation {
+    fn read(path: &PathBuf) -> Self {
+        let tag = utils::get_tags(path).ok();
+        Self {
+            title: tag
+                .as_ref()
+                .and_then(|tag| tag.title().map(|title| title.to_string())),
+            artist: tag
+                .as_ref()
+                .and_then(|tag| tag.artist().map(|artist| artist.to_string())),
+            album: tag
+                .as_ref()
+                .and_then(|tag| tag.album().map(|album| album.to_string())),
+            track: tag.as_ref().and_then(|tag| tag.track()),
+            duration: utils::get_total_duration(path).ok(),
+        }
+    }
+    #[doc = "`Artist - Title [mm:ss]` with the parts the tags have"]
+    fn describe(&self) -> String {
+        let title = match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{artist} - {title}"),
+            (None, Some(title)) => title.clone(),
+            (Some(artist), None) => artist.clone(),
+            (None, None) => String::new(),
+        };
+        match self.duration {
+            Some(duration) if title.is_empty() => {
+                format!("[{}]", utils::format_timestamp(duration))
+            }
+            Some(duration) => format!("{title} [{}]", utils::format_timestamp(duration)),
+            None => title,
+        }
+    }
+}
+
+#[doc = "Annotation of every probed file of the directory, None for those that are not playable audio"]
+type Annotations = Arc<Mutex<HashMap<PathBuf, Option<Annotation>>>>;
+
+#[doc = "Annotation of a playable file, empty for playlists and CUE sheets which have no tags"]
+fn probe(path: &Path) -> Option<Annotation> {
+    if is_playlist(path) || is_cue_sheet(path) {
+        return Some(Annotation::default());
+    }
+    verify_audio_file(path).map(|path| Annotation::read(&path))
+}
+
+#[doc = "Probe and read the tags of `paths` in a background thread, which stops once the explorer dropped the result"]
+fn annotate(paths: Vec<PathBuf>) -> Annotations {
+    let annotations = Annotations::default();
+    let shared = annotations.clone();
+    std::thread::spawn(move || {
+        for path in paths {
+            if Arc::strong_count(&shared) == 1 {
+                return;
+            }
+            let annotation = probe(&path);
+            if let Ok(mut annotations) = shared.lock() {
+                annotations.insert(path, annotation);
+            }
+        }
+    });
+    annotations
+}
+
+#[doc = "Files below a directory in the order they were walked, with their annotation like `Annotations`"]
+type Library = Arc<Mutex<Vec<(PathBuf, Option<Annotation>)>>>;
+
+#[doc = "Walk every directory below `dir` in a background thread, annotating the files like `annotate`"]
+fn annotate_tree(dir: PathBuf) -> Library {
+    let annotations = Library::default();
+    let shared = annotations.clone();
+    std::thread::spawn(move || {
+        let mut dirs = vec![dir];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut entries: Vec<std::fs::DirEntry> =
+                entries.filter_map(|entry| entry.ok()).collect();
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                if Arc::strong_count(&shared) == 1 {
+                    return;
+                }
+                // Symbolic links to directories are not followed, a loop would never end
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => dirs.push(entry.path()),
+                    Ok(_) => {
+                        let annotation = probe(&entry.path());
+                        if let Ok(mut annotations) = shared.lock() {
+                            annotations.push((entry.path(), annotation));
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+    });
+    annotations
+}
+
+#[doc = "Score of `query` matched as a subsequence of `text`, both lowercase, None when it does not match. Consecutive letters and starts of words score more, gaps score less"]
+fn fuzzy_score(query: &[char], text: &[char]) -> Option<i64> {
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for &letter in query {
+        let found = (position..text.len()).find(|index| text[*index] == letter)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= (found - position).min(5) as i64;
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[doc = "Entry the search can find with the lowercase name and tags the query is matched against"]
+#[derive(Debug, Clone)]
+struct Candidate {
+    entry: Entry,
+    annotation: Option<Annotation>,
+    haystack: Vec<char>,
+}
+
+impl Candidate {
+    fn new(entry: Entry, annotation: Option<Annotation>) -> Self {
+        let tags = annotation.as_ref().map_or(String::new(), |annotation| {
+            [&annotation.artist, &annotation.title, &annotation.album]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ")
+        });
+        let haystack = format!("{} {tags}", entry.name)
+            .to_lowercase()
+            .chars()
+            .collect();
+        Self {
+            entry,
+            annotation,
+            haystack,
+        }
+    }
+}
+
+#[doc = "Fuzzy search typed after `/`, over the listing or over every file below the directory"]
+#[derive(Debug, Clone, Default)]
+struct Search {
+    query: String,
+    recursive: bool,
+    #[doc = "Files below the directory, walked in the background the first time the search turns recursive"]
+    library: Option<Library>,
+    #[doc = "Playable files of `library` taken in so far, sorted by name"]
+    found: Vec<Candidate>,
+    #[doc = "Number of files of `library` taken in `found`"]
+    scanned: usize,
+    #[doc = "When `found` last took in files of `library`"]
+    rescanned: Option<Instant>,
+    results: Vec<(Entry, Option<Annotation>)>,
+    selected: usize,
+}
+
+impl Search {
+    #[doc = "Take in the files the scan found since the last call, not more often than every `RESCAN_FILES` files or `RESCAN_INTERVAL`, true when some were"]
+    fn rescan(&mut self, cwd: &Path) -> bool {
+        let Some(Ok(library)) = self.library.as_ref().map(|library| library.lock()) else {
+            return false;
+        };
+        let waiting = library.len().saturating_sub(self.scanned);
+        let due = waiting >= RESCAN_FILES
+            || self
+                .rescanned
+                .is_none_or(|rescanned| rescanned.elapsed() >= RESCAN_INTERVAL);
+        if waiting == 0 || !due {
+            return false;
+        }
+        self.found.extend(
+            library[self.scanned..]
+                .iter()
+                .filter_map(|(path, annotation)| {
+                    let annotation = annotation.clone()?;
+                    let name = path.strip_prefix(cwd).unwrap_or(path);
+                    Some(Candidate::new(
+                        Entry {
+                            name: name.to_string_lossy().to_string(),
+                            path: path.clone(),
+                            is_dir: false,
+                            modified: None,
+                        },
+                        Some(annotation),
+                    ))
+                }),
+        );
+        self.scanned = library.len();
+        drop(library);
+        self.found.sort_by(|a, b| a.entry.name.cmp(&b.entry.name));
+        self.rescanned = Some(Instant::now());
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+    #[doc = "Playlists and CUE sheets are known from their extension, they are never probed"]
+    fn is_list(&self) -> bool {
+        is_playlist(&self.path) || is_cue_sheet(&self.path)
+    }
+}
+
+#[doc = "Directory browser listing playable audio with its tags, optionally hiding every other file"]
+#[derive(Debug, Clone)]
+pub struct FileExplorer {
+    cwd: PathBuf,
+    #[doc = "Every entry of the directory, `../` first when there is a parent"]
+    entries: Vec<Entry>,
+    #[doc = "Indices in `entries` of what is listed, filtered and sorted"]
+    visible: Vec<usize>,
+    selected: usize,
+    audio_only: bool,
+    sort: SortBy,
+    #[doc = "Highlighted order while the sort menu is open"]
+    sort_menu: Option<usize>,
+    #[doc = "Search in progress, its results are listed instead of the directory"]
+    search: Option<Search>,
+    annotations: Annotations,
+    #[doc = "Number of annotations the listing was built with, it is rebuilt when more arrived"]
+    annotated: usize,
+    color: Color,
+}
+
+impl FileExplorer {
+    #[doc = "Explorer of the working directory"]
+    pub fn new() -> Result<Self> {
+        let mut explorer = Self {
+            cwd: std::env::current_dir().map_err(|e| Error::Io(PathBuf::from("."), e))?,
+            entries: Vec::new(),
+            visible: Vec::new(),
+            selected: 0,
+            audio_only: false,
+            sort: SortBy::default(),
+            sort_menu: None,
+            search: None,
+            annotations: Annotations::default(),
+            annotated: 0,
+            color: Color::White,
+        };
+        explorer.read_dir()?;
+        Ok(explorer)
+    }
+    pub fn set_cwd(&mut self, cwd: impl Into<PathBuf>) -> Result<()> {
+        let previous = std::mem::replace(&mut self.cwd, cwd.into());
+        if let Err(e) = self.read_dir() {
+            self.cwd = previous;
+            return Err(e);
+        }
+        self.selected = 0;
+        Ok(())
+    }
+    pub fn cwd(&self) -> &PathBuf {
+        &self.cwd
+    }
+    #[doc = "Selected entry, or search result while searching, None when nothing is listed"]
+    pub fn current(&self) -> Option<&Entry> {
+        match &self.search {
+            Some(search) => search.results.get(search.selected).map(|(entry, _)| entry),
+            None => self
+                .visible
+                .get(self.selected)
+                .map(|index| &self.entries[*index]),
+        }
+    }
+    pub fn len(&self) -> usize {
+        match &self.search {
+            Some(search) => search.results.len(),
+            None => self.visible.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn selected_idx(&self) -> usize {
+        self.search
+            .as_ref()
+            .map_or(self.selected, |search| search.selected)
+    }
+    pub fn set_selected_idx(&mut self, selected: usize) {
+        let selected = selected.min(self.len().saturating_sub(1));
+        match self.search.as_mut() {
+            Some(search) => search.selected = selected,
+            None => self.selected = selected,
+        }
+    }
+    pub fn set_color(&mut self, color: Color) -> &mut Self {
+        self.color = color;
+        self
+    }
+    pub fn audio_only(&self) -> bool {
+        self.audio_only
+    }
+    #[doc = "Show only directories, playlists, CUE sheets and playable audio, or every file again"]
+    pub fn toggle_audio_only(&mut self) {
+        self.audio_only = !self.audio_only;
+        self.refresh();
+    }
+    pub fn sort(&self) -> SortBy {
+        self.sort
+    }
+    pub fn set_sort(&mut self, sort: SortBy) -> &mut Self {
+        self.sort = sort;
+        self.refresh();
+        self
+    }
+    pub fn sort_menu_open(&self) -> bool {
+        self.sort_menu.is_some()
+    }
+    pub fn open_sort_menu(&mut self) {
+        self.sort_menu = SortBy::ALL.iter().position(|sort| *sort == self.sort);
+    }
+    #[doc = "Pick an order in the sort menu, Enter applies it and Esc closes the menu"]
+    pub fn sort_menu_key(&mut self, key_event: KeyEvent) {
+        let Some(highlighted) = self.sort_menu.as_mut() else {
+            return;
+        };
+        if key_event.kind != KeyEventKind::Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                *highlighted = highlighted.checked_sub(1).unwrap_or(SortBy::ALL.len() - 1)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                *highlighted = (*highlighted + 1) % SortBy::ALL.len()
+            }
+            KeyCode::Enter => {
+                let sort = SortBy::ALL[*highlighted];
+                self.sort_menu = None;
+                self.set_sort(sort);
+            }
+            KeyCode::Esc | KeyCode::Char('o') => self.sort_menu = None,
+            _ => {}
+        }
+    }
+    pub fn searching(&self) -> bool {
+        self.search.is_some()
+    }
+    pub fn open_search(&mut self) {
+        self.search = Some(Search::default());
+        self.refresh_search(false);
+    }
+    pub fn close_search(&mut self) {
+        self.search = None;
+    }
+    #[doc = "Edit the query, move through the results or toggle the recursive search, Esc closes the search"]
+    pub fn search_key(&mut self, key_event: KeyEvent) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        if !matches!(key_event.kind, KeyEventKind::Press | KeyEventKind::Repeat) {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Esc => self.search = None,
+            KeyCode::Tab => {
+                search.recursive = !search.recursive;
+                if search.library.is_none() {
+                    search.library = Some(annotate_tree(self.cwd.clone()));
+                }
+                self.refresh_search(false);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                self.refresh_search(false);
+            }
+            KeyCode::Char(letter)
+                if !key_event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                search.query.push(letter);
+                self.refresh_search(false);
+            }
+            KeyCode::Up => {
+                let _ = self.handle(Input::Up);
+            }
+            KeyCode::Down => {
+                let _ = self.handle(Input::Down);
+            }
+            KeyCode::PageUp => {
+                let _ = self.handle(Input::PageUp);
+            }
+            KeyCode::PageDown => {
+                let _ = self.handle(Input::PageDown);
+            }
+            _ => {}
+        }
+    }
+    #[doc = "Close the search and show the selected result in its directory, or open it when it is one"]
+    pub fn jump_to_result(&mut self) -> Result<()> {
+        let Some(entry) = self.current().cloned() else {
+            self.search = None;
+            return Ok(());
+        };
+        self.search = None;
+        if entry.is_dir {
+            return self.set_cwd(entry.path);
+        }
+        if let Some(parent) = entry.path.parent().filter(|parent| *parent != self.cwd) {
+            self.set_cwd(parent.to_path_buf())?;
+        }
+        self.select_path(&entry.path);
+        Ok(())
+    }
+    pub fn handle(&mut self, input: impl Into<Input>) -> Result<()> {
+        let last = self.len().saturating_sub(1);
+        let selected = match self.search.as_mut() {
+            Some(search) => &mut search.selected,
+            None => &mut self.selected,
+        };
+        match input.into() {
+            Input::Up => *selected = selected.checked_sub(1).unwrap_or(last),
+            Input::Down => *selected = if *selected >= last { 0 } else { *selected + 1 },
+            Input::Home => *selected = 0,
+            Input::End => *selected = last,
+            Input::PageUp => *selected = selected.saturating_sub(SCROLL_COUNT),
+            Input::PageDown => *selected = (*selected + SCROLL_COUNT).min(last),
+            // The search keeps its results until it is closed
+            Input::Left | Input::Right if self.search.is_some() => {}
+            Input::Left => {
+                if let Some(parent) = self.cwd.parent().map(Path::to_path_buf) {
+                    let child = self.cwd.clone();
+                    self.set_cwd(parent)?;
+                    // Keep the directory we came from under the cursor
+                    self.select_path(&child);
+                }
+            }
+            Input::Right => {
+                if let Some(dir) = self
+                    .current()
+                    .filter(|entry| entry.is_dir)
+                    .map(|entry| entry.path.clone())
+                {
+                    self.set_cwd(dir)?;
+                }
+            }
+            Input::None => {}
+        }
+        Ok(())
+    }
+    #[doc = "Take in the annotations loaded since the last frame, the selection stays on the same entry"]
+    pub fn update(&mut self) {
+        let annotated = self
+            .annotations
+            .lock()
+            .map_or(self.annotated, |annotations| annotations.len());
+        if annotated != self.annotated {
+            self.annotated = annotated;
+            self.refresh();
+            self.refresh_search(true);
+        }
+        if let Some(search) = self.search.as_mut()
+            && search.rescan(&self.cwd)
+        {
+            search.recursive.then(|| self.refresh_search(true));
+        }
+    }
+    #[doc = "Entries of the listing the search looks through with what is known of them"]
+    fn listing_candidates(&self) -> Vec<Candidate> {
+        let Ok(annotations) = self.annotations.lock() else {
+            return Vec::new();
+        };
+        self.visible
+            .iter()
+            .map(|index| &self.entries[*index])
+            .filter(|entry| entry.name != "../")
+            .map(|entry| {
+                Candidate::new(
+                    entry.clone(),
+                    annotations.get(&entry.path).cloned().flatten(),
+                )
+            })
+            .collect()
+    }
+    #[doc = "Match the query against the names and tags of the candidates, best matches first. The selection moves to the best match unless `keep_selection` keeps it on the same entry"]
+    fn refresh_search(&mut self, keep_selection: bool) {
+        let listing = match &self.search {
+            None => return,
+            Some(search) if search.recursive => Vec::new(),
+            Some(_) => self.listing_candidates(),
+        };
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let selected = keep_selection
+            .then(|| search.results.get(search.selected))
+            .flatten()
+            .map(|(entry, _)| entry.path.clone());
+        let query: Vec<char> = search
+            .query
+            .to_lowercase()
+            .chars()
+            .filter(|letter| !letter.is_whitespace())
+            .collect();
+        let candidates = if search.recursive {
+            &search.found
+        } else {
+            &listing
+        };
+        let mut results: Vec<(i64, &Candidate)> = candidates
+            .iter()
+            .filter_map(|candidate| Some((fuzzy_score(&query, &candidate.haystack)?, candidate)))
+            .collect();
+        // Stable, equal scores keep the order of the listing
+        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        search.results = results
+            .into_iter()
+            .map(|(_, candidate)| (candidate.entry.clone(), candidate.annotation.clone()))
+            .collect();
+        search.selected = selected
+            .and_then(|selected| {
+                search
+                    .results
+                    .iter()
+                    .position(|(entry, _)| entry.path == selected)
+            })
+            .unwrap_or(0);
+    }
+    fn select_path(&mut self, path: &Path) {
+        if let Some(selected) = self
+            .visible
+            .iter()
+            .position(|index| self.entries[*index].path == path)
+        {
+            self.selected = selected;
+        }
+    }
+    fn read_dir(&mut self) -> Result<()> {
+        let mut entries: Vec<Entry> = std::fs::read_dir(&self.cwd)
+            .map_err(|e| Error::Io(self.cwd.clone(), e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let path = entry.path();
+                let metadata = path.metadata().ok();
+                let is_dir = metadata.as_ref().is_some_and(|metadata| metadata.is_dir());
+                let name = entry.file_name().to_string_lossy().to_string();
+                Entry {
+                    name: if is_dir { format!("{name}/") } else { name },
+                    path,
+                    is_dir,
+                    modified: metadata.and_then(|metadata| metadata.modified().ok()),
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        if let Some(parent) = self.cwd.parent() {
+            entries.insert(
+                0,
+                Entry {
+                    name: "../".to_string(),
+                    path: parent.to_path_buf(),
+                    is_dir: true,
+                    modified: None,
+                },
+            );
+        }
+        self.annotations = annotate(
+            entries
+                .iter()
+                .filter(|entry| !entry.is_dir && !entry.is_list())
+                .map(|entry| entry.path.clone())
+                .collect(),
+        );
+        // The old listing indexes the old entries
+        self.visible.clear();
+        self.entries = entries;
+        self.annotated = 0;
+        self.refresh();
+        Ok(())
+    }
+    #[doc = "Rebuild the listing from the filter, the order and the annotations known so far"]
+    fn refresh(&mut self) {
+        // The directory cursor, not the search one
+        let selected = self
+            .visible
+            .get(self.selected)
+            .map(|index| self.entries[*index].path.clone());
+        let Ok(annotations) = self.annotations.lock() else {
+            return;
+        };
+        // Files not probed yet stay listed until they are known not to be audio, the cursor does not jump when they arrive
+        let mut visible: Vec<usize> = (0..self.entries.len())
+            .filter(|index| {
+                let entry = &self.entries[*index];
+                !self.audio_only
+                    || entry.is_dir
+                    || entry.is_list()
+                    || annotations.get(&entry.path).is_none_or(Option::is_some)
+            })
+            .collect();
+        let annotation = |index: &usize| {
+            annotations
+                .get(&self.entries[*index].path)
+                .and_then(Option::as_ref)
+        };
+        let entries = &self.entries;
+        visible.sort_by(|a, b| {
+            let (first, second) = (&entries[*a], &entries[*b]);
+            let by_kind = (second.name == "../")
+                .cmp(&(first.name == "../"))
+                .then(second.is_dir.cmp(&first.is_dir));
+            let by_sort = match self.sort {
+                _ if first.is_dir => std::cmp::Ordering::Equal,
+                SortBy::Name => std::cmp::Ordering::Equal,
+                SortBy::Modified => second.modified.cmp(&first.modified),
+                // Files without the information come last
+                SortBy::Duration => {
+                    let duration =
+                        |index| annotation(index).and_then(|annotation| annotation.duration);
+                    duration(a)
+                        .is_none()
+                        .cmp(&duration(b).is_none())
+                        .then(duration(a).cmp(&duration(b)))
+                }
+                SortBy::TrackNumber => {
+                    let track = |index| annotation(index).and_then(|annotation| annotation.track);
+                    track(a)
+                        .is_none()
+                        .cmp(&track(b).is_none())
+                        .then(track(a).cmp(&track(b)))
+                }
+            };
+            by_kind
+                .then(by_sort)
+                .then_with(|| first.name.cmp(&second.name))
+        });
+        drop(annotations);
+        self.visible = visible;
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+        if let Some(selected) = selected {
+            self.select_path(&selected);
+        }
+    }
+    #[doc = "Line of an entry with what its tags tell, or a pending marker while it is not probed yet"]
+    fn item(
+        &self,
+        entry: &Entry,
+        annotation: Option<&Annotation>,
+        pending: bool,
+    ) -> ListItem<'static> {
+        let style = Style::default().fg(if entry.is_dir {
+            Color::LightBlue
+        } else {
+            Color::White
+        });
+        let mut spans = vec![Span::styled(entry.name.clone(), style)];
+        if pending {
+            spans.push(Span::styled("  …", Style::default().fg(Color::DarkGray)));
+        } else if let Some(description) = annotation
+            .map(Annotation::describe)
+            .filter(|description| !description.is_empty())
+        {
+            spans.push(Span::styled(
+                format!("  {description}"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        ListItem::new(Line::from(spans))
+    }
+}
+
+impl Widget for &FileExplorer {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if let Some(search) = &self.search {
+            let items: Vec<ListItem> = search
+                .results
+                .iter()
+                .map(|(entry, annotation)| self.item(entry, annotation.as_ref(), false))
+                .collect();
+            let scope = if search.recursive {
+                format!("below {}", self.cwd.display())
+            } else {
+                format!("in {}", self.cwd.display())
+            };
+            let mut state = ListState::default().with_selected(Some(search.selected));
+            StatefulWidget::render(
+                List::new(items)
+                    .block(
+                        Block::bordered()
+                            .title_top(format!(
+                                "/{}▏ - {} matches {scope}",
+                                search.query,
+                                search.results.len()
+                            ))
+                            .title_bottom(
+                                "[Play <Enter> | Enqueue <Ctrl+a> | Recursive <Tab> | Close <Esc>]",
+                            ),
+                    )
+                    .style(Style::default().fg(self.color))
+                    .highlight_style(Style::default().bg(Color::DarkGray)),
+                area,
+                buf,
+                &mut state,
+            );
+            return;
+        }
+        let items: Vec<ListItem> = match self.annotations.lock() {
+            Ok(annotations) => self
+                .visible
+                .iter()
+                .map(|index| {
+                    let entry = &self.entries[*index];
+                    let annotation = annotations.get(&entry.path);
+                    let pending = self.audio_only
+                        && annotation.is_none()
+                        && !entry.is_dir
+                        && !entry.is_list();
+                    self.item(entry, annotation.and_then(Option::as_ref), pending)
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let title = format!(
+            "{} - Sort: {}{}",
+            self.cwd.display(),
+            self.sort.name(),
+            if self.audio_only { " | Audio Only" } else { "" }
+        );
+        // A fresh state scrolls just enough to show the selection, the mouse handler relies on it
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(
+            List::new(items)
+                .block(Block::bordered().title_top(title).title_bottom(
+                    "[Play <Enter> | Enqueue <a> | Search </> | Audio Only <f> | Sort <o>]",
+                ))
+                .style(Style::default().fg(self.color))
+                .highlight_style(Style::default().bg(Color::DarkGray)),
+            area,
+            buf,
+            &mut state,
+        );
+        if let Some(highlighted) = self.sort_menu {
+            let popup = centered_rect(area, 30, SortBy::ALL.len() as u16 + 2);
+            Clear.render(popup, buf);
+            let mut state = ListState::default().with_selected(Some(highlighted));
+            StatefulWidget::render(
+                List::new(SortBy::ALL.iter().map(|sort| ListItem::new(sort.name())))
+                    .block(
+                        Block::bordered()
+                            .title("[Sort By]")
+                            .title_bottom("[Apply <Enter> | Cancel <Esc>]")
+                            .border_type(BorderType::Rounded),
+                    )
+                    .style(Style::default().fg(Color::Yellow))
+                    .highlight_symbol("> "),
+                popup,
+                buf,
+                &mut state,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use ratatui::crossterm::event::{KeyEventState, MouseEvent, MouseEventKind};
+
+    use super::*;
+
+    #[doc = "Fresh directory under the system temporary directory holding `dirs` and empty `files`"]
+    fn tree(name: &str, dirs: &[&str], files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("explorer-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for dir in dirs {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in files {
+            File::create(root.join(file)).unwrap();
+        }
+        root
+    }
+
+    fn explorer(cwd: &Path) -> FileExplorer {
+        let mut explorer = FileExplorer::new().unwrap();
+        explorer.set_cwd(cwd).unwrap();
+        explorer
+    }
+
+    fn names(explorer: &FileExplorer) -> Vec<&str> {
+        explorer
+            .visible
+            .iter()
+            .map(|index| explorer.entries[*index].name())
+            .collect()
+    }
+
+    #[doc = "Replace what the background probe found with `annotations`, in place of waiting for it"]
+    fn annotate_with(explorer: &mut FileExplorer, annotations: Vec<(&str, Option<Annotation>)>) {
+        explorer.annotations = Arc::new(Mutex::new(
+            annotations
+                .into_iter()
+                .map(|(name, annotation)| (explorer.cwd.join(name), annotation))
+                .collect(),
+        ));
+        explorer.refresh();
+    }
+
+    fn key(code: KeyCode, kind: KeyEventKind) -> Event {
+        Event::Key(KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind,
+            state: KeyEventState::NONE,
+        })
+    }
+
+    #[test]
+    fn input_maps_vi_keys_and_arrows() {
+        for (code, input) in [
+            (KeyCode::Char('j'), Input::Down),
+            (KeyCode::Down, Input::Down),
+            (KeyCode::Char('k'), Input::Up),
+            (KeyCode::Char('h'), Input::Left),
+            (KeyCode::Backspace, Input::Left),
+            (KeyCode::Char('l'), Input::Right),
+            (KeyCode::Enter, Input::Right),
+            (KeyCode::Home, Input::Home),
+            (KeyCode::PageDown, Input::PageDown),
+            (KeyCode::Char('x'), Input::None),
+        ] {
+            assert_eq!(Input::from(&key(code, KeyEventKind::Press)), input);
+        }
+        assert_eq!(
+            Input::from(&key(KeyCode::Down, KeyEventKind::Repeat)),
+            Input::Down
+        );
+        assert_eq!(
+            Input::from(&key(KeyCode::Down, KeyEventKind::Release)),
+            Input::None
+        );
+        let scroll = Event::Mouse(MouseEvent {
+            kind: MouseEventKind::ScrollDown,
+            column: 0,
+            row: 0,
+            modifiers: KeyModifiers::NONE,
+        });
+        assert_eq!(Input::from(&scroll), Input::None);
+    }
+
+    #[test]
+    fn refresh_lists_parent_then_directories_then_files() {
+        let root = tree("order", &["b", "a"], &["z.txt", "c.txt"]);
+        let explorer = explorer(&root);
+        assert_eq!(names(&explorer), ["../", "a/", "b/", "c.txt", "z.txt"]);
+    }
+
+    #[test]
+    fn refresh_sorts_files_by_duration_and_track_unknown_last() {
+        let root = tree("sort", &["dir"], &["a.flac", "b.flac", "c.flac"]);
+        let mut explorer = explorer(&root);
+        let annotation = |track, secs: Option<u64>| {
+            Some(Annotation {
+                track,
+                duration: secs.map(Duration::from_secs),
+                ..Annotation::default()
+            })
+        };
+        annotate_with(
+            &mut explorer,
+            vec![
+                ("a.flac", annotation(Some(3), Some(200))),
+                ("b.flac", annotation(None, Some(100))),
+                ("c.flac", annotation(Some(1), None)),
+            ],
+        );
+        explorer.set_sort(SortBy::Duration);
+        assert_eq!(
+            names(&explorer),
+            ["../", "dir/", "b.flac", "a.flac", "c.flac"]
+        );
+        explorer.set_sort(SortBy::TrackNumber);
+        assert_eq!(
+            names(&explorer),
+            ["../", "dir/", "c.flac", "a.flac", "b.flac"]
+        );
+    }
+
+    #[test]
+    fn refresh_sorts_files_by_modified_newest_first() {
+        let root = tree("modified", &[], &["old.flac", "new.flac"]);
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (file, secs) in [("old.flac", 1_000), ("new.flac", 2_000)] {
+            File::options()
+                .write(true)
+                .open(root.join(file))
+                .unwrap()
+                .set_modified(epoch + Duration::from_secs(secs))
+                .unwrap();
+        }
+        let mut explorer = explorer(&root);
+        explorer.set_sort(SortBy::Modified);
+        assert_eq!(names(&explorer), ["../", "new.flac", "old.flac"]);
+    }
+
+    #[test]
+    fn audio_only_keeps_unprobed_files_until_they_are_known() {
+        let root = tree("filter", &["dir"], &["notes.txt", "song.flac", "list.m3u"]);
+        let mut explorer = explorer(&root);
+        annotate_with(&mut explorer, vec![]);
+        explorer.toggle_audio_only();
+        assert_eq!(
+            names(&explorer),
+            ["../", "dir/", "list.m3u", "notes.txt", "song.flac"]
+        );
+        explorer.select_path(&root.join("song.flac"));
+        annotate_with(
+            &mut explorer,
+            vec![
+                ("notes.txt", None),
+                ("song.flac", Some(Annotation::default())),
+            ],
+        );
+        assert_eq!(names(&explorer), ["../", "dir/", "list.m3u", "song.flac"]);
+        assert_eq!(explorer.current().map(Entry::name), Some("song.flac"));
+    }
+
+    #[test]
+    fn select_path_moves_to_the_entry_and_ignores_unknown_paths() {
+        let root = tree("select", &["a"], &["b.txt", "c.txt"]);
+        let mut explorer = explorer(&root);
+        explorer.select_path(&root.join("c.txt"));
+        assert_eq!(explorer.current().map(Entry::name), Some("c.txt"));
+        explorer.select_path(&root.join("missing.txt"));
+        assert_eq!(explorer.current().map(Entry::name), Some("c.txt"));
+    }
+
+    fn type_query(explorer: &mut FileExplorer, query: &str) {
+        for letter in query.chars() {
+            explorer.search_key(KeyEvent::new(KeyCode::Char(letter), KeyModifiers::NONE));
+        }
+    }
+
+    #[test]
+    fn search_selects_the_best_match_when_the_query_changes() {
+        let root = tree("search", &[], &["abc.flac", "ab.flac", "xab.flac"]);
+        let mut explorer = explorer(&root);
+        explorer.open_search();
+        type_query(&mut explorer, "a");
+        explorer.handle(Input::Down).unwrap();
+        type_query(&mut explorer, "bc");
+        assert_eq!(explorer.selected_idx(), 0);
+        assert_eq!(explorer.current().map(Entry::name), Some("abc.flac"));
+    }
+
+    #[test]
+    fn refresh_while_searching_keeps_the_directory_cursor() {
+        let root = tree("cursor", &[], &["a.flac", "b.flac", "c.flac"]);
+        let mut explorer = explorer(&root);
+        explorer.select_path(&root.join("b.flac"));
+        explorer.open_search();
+        type_query(&mut explorer, "c");
+        explorer.refresh();
+        explorer.close_search();
+        assert_eq!(explorer.current().map(Entry::name), Some("b.flac"));
+    }
+
+    #[test]
+    fn left_keeps_the_directory_it_came_from_selected() {
+        let root = tree("left", &["a", "b"], &[]);
+        let mut explorer = explorer(&root.join("b"));
+        explorer.handle(Input::Left).unwrap();
+        assert_eq!(explorer.cwd(), &root);
+        assert_eq!(explorer.current().map(Entry::name), Some("b/"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Instant;
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType};
+
+use crate::audio::{AudioPlayer, Levels};
+
+#[doc = "Range of the meters in dBFS, the top leaves room for a volume of 2"]
+const RANGE: (f32, f32) = (-60., 6.);
+
+#[doc = "dB a held peak falls by in a second"]
+const PEAK_FALL_RATE: f32 = 20.;
+
+#[doc = "dB the RMS bar falls by in a second"]
+const RMS_FALL_RATE: f32 = 40.;
+
+fn to_db(level: f32) -> f32 {
+    (20. * level.max(f32::MIN_POSITIVE).log10()).max(RANGE.0)
+}
+
+fn db_color(db: f32) -> Color {
+    match db {
+        db if db >= 0. => Color::Red,
+        db if db >= -9. => Color::Yellow,
+        _ => Color::Green,
+    }
+}
+
+#[doc = "Stereo peak and RMS meters of the output with the clip and limiter indicators"]
+#[derive(Debug, Clone)]
+pub struct LevelMeters {
+    #[doc = "Smoothed levels in dBFS of the left and right channels"]
+    peak: [f32; 2],
+    rms: [f32; 2],
+    clipped: bool,
+    limiting: bool,
+    last_update: Option<Instant>,
+}
+
+impl Default for LevelMeters {
+    fn default() -> Self {
+        Self {
+            peak: [RANGE.0; 2],
+            rms: [RANGE.0; 2],
+            clipped: false,
+            limiting: false,
+            last_update: None,
+        }
+    }
+}
+
+impl LevelMeters {
+    #[doc = "Read the levels of the player, the bars rise at once and fall smoothly"]
+    pub fn update(&mut self, audio_player: &AudioPlayer) {
+        let now = Instant::now();
+        let elapsed = self
+            .last_update
+            .map_or(0., |last| now.duration_since(last).as_secs_f32());
+        self.last_update = Some(now);
+        let Levels {
+            peak,
+            rms,
+            clipped,
+            limiting,
+        } = audio_player.levels();
+        for side in 0..2 {
+            self.peak[side] = to_db(peak[side]).max(self.peak[side] - PEAK_FALL_RATE * elapsed);
+            self.rms[side] = to_db(rms[side]).max(self.rms[side] - RMS_FALL_RATE * elapsed);
+        }
+        (self.clipped, self.limiting) = (clipped, limiting);
+    }
+}
+
+#[doc = "Column of `width` a level in dBFS reaches"]
+fn column(db: f32, width: u16) -> u16 {
+    (((db - RANGE.0) / (RANGE.1 - RANGE.0)).clamp(0., 1.) * width as f32).round() as u16
+}
+
+impl Widget for LevelMeters {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .title("[Levels]")
+            .title_bottom("[Limiter <l> | Reset Clip <k>]")
+            .border_type(BorderType::Rounded)
+            .style(Style::default().fg(Color::Blue));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let [labels, meters, indicators] = Layout::horizontal([
+            Constraint::Length(2),
+            Constraint::Min(0),
+            Constraint::Length(16),
+        ])
+        .areas(inner);
+        let db_width = 10;
+        for (side, (name, y)) in [("L", inner.y), ("R", inner.y + 1)].into_iter().enumerate() {
+            if y >= inner.bottom() {
+                break;
+            }
+            buf.set_string(labels.x, y, name, Style::default().fg(Color::Yellow));
+            let width = meters.width.saturating_sub(db_width);
+            let (rms, peak) = (
+                column(self.rms[side], width),
+                column(self.peak[side], width),
+            );
+            for x in 0..width {
+                let db = RANGE.0 + (x as f32 + 0.5) / width as f32 * (RANGE.1 - RANGE.0);
+                let (symbol, color) = match x {
+                    x if x < rms => ("█", db_color(db)),
+                    x if x + 1 == peak => ("▏", db_color(db)),
+                    x if x == column(0., width) => ("┊", Color::DarkGray),
+                    _ => ("·", Color::DarkGray),
+                };
+                buf[(meters.x + x, y)].set_symbol(symbol).set_fg(color);
+            }
+            buf.set_string(
+                meters.x + width,
+                y,
+                format!(" {:>+6.1} dB", self.peak[side]),
+                Style::default().fg(db_color(self.peak[side])),
+            );
+        }
+        let indicator = |on: bool, color: Color| {
+            if on {
+                Style::default().fg(Color::Black).bg(color)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            }
+        };
+        buf.set_string(
+            indicators.x + 2,
+            indicators.y,
+            " CLIP ",
+            indicator(self.clipped, Color::Red),
+        );
+        if indicators.height > 1 {
+            buf.set_string(
+                indicators.x + 2,
+                indicators.y + 1,
+                " LIMIT ",
+                indicator(self.limiting, Color::Yellow),
+            );
+        }
+    }
+}
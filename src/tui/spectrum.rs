@@ -0,0 +1,185 @@
+use std::time::Instant;
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, BorderType};
+
+use crate::audio::AudioPlayer;
+
+#[doc = "Frequency bands computed by the analyzer, spread over the width of the pane"]
+const BANDS: usize = 128;
+
+#[doc = "Levels a bar falls by in a second"]
+const FALL_RATE: f32 = 1.5;
+
+#[doc = "Levels a held peak falls by in a second"]
+const PEAK_FALL_RATE: f32 = 0.3;
+
+#[doc = "Characters of a bar filled by 1 to 8 eighths of a cell from the bottom"]
+const EIGHTHS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SpectrumMode {
+    #[default]
+    Bars,
+    #[doc = "Bars growing up and down from the middle of the pane"]
+    Mirrored,
+    #[doc = "Bars with a marker on the highest level reached, falling slowly"]
+    PeakHold,
+}
+
+impl SpectrumMode {
+    pub fn next(&self) -> Self {
+        match self {
+            SpectrumMode::Bars => SpectrumMode::Mirrored,
+            SpectrumMode::Mirrored => SpectrumMode::PeakHold,
+            SpectrumMode::PeakHold => SpectrumMode::Bars,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpectrumMode::Bars => "Bars",
+            SpectrumMode::Mirrored => "Mirrored",
+            SpectrumMode::PeakHold => "Peak Hold",
+        }
+    }
+}
+
+#[doc = "Log-frequency spectrum of what is playing, toggled with <v>"]
+#[derive(Debug, Default, Clone)]
+pub struct Spectrum {
+    pub visible: bool,
+    pub mode: SpectrumMode,
+    levels: Vec<f32>,
+    peaks: Vec<f32>,
+    last_update: Option<Instant>,
+}
+
+impl Spectrum {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.levels.clear();
+        self.peaks.clear();
+        self.last_update = None;
+    }
+    #[doc = "Read the analyzer, bars rise at once and fall smoothly"]
+    pub fn update(&mut self, audio_player: &AudioPlayer) {
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        let elapsed = self
+            .last_update
+            .map_or(0., |last| now.duration_since(last).as_secs_f32());
+        self.last_update = Some(now);
+        let spectrum = audio_player.spectrum(BANDS);
+        self.levels.resize(BANDS, 0.);
+        self.peaks.resize(BANDS, 0.);
+        for ((level, peak), new) in self.levels.iter_mut().zip(&mut self.peaks).zip(spectrum) {
+            *level = new.max(*level - FALL_RATE * elapsed);
+            *peak = new.max(*peak - PEAK_FALL_RATE * elapsed);
+        }
+    }
+    #[doc = "Highest level of the bands shown in column `x` of a `width` wide pane"]
+    fn column(levels: &[f32], x: u16, width: u16) -> f32 {
+        let start = x as usize * levels.len() / width as usize;
+        let end = ((x as usize + 1) * levels.len() / width as usize).max(start + 1);
+        levels
+            .get(start..end.min(levels.len()))
+            .map_or(0., |bands| bands.iter().copied().fold(0., f32::max))
+    }
+}
+
+fn level_color(ratio: f32) -> Color {
+    match ratio {
+        ratio if ratio > 0.85 => Color::Red,
+        ratio if ratio > 0.6 => Color::Yellow,
+        _ => Color::Green,
+    }
+}
+
+#[doc = "Draw a bar of `level` eighths from the bottom of `area` upwards, or from its top downwards"]
+fn draw_bar(buf: &mut Buffer, area: Rect, x: u16, level: usize, downwards: bool) {
+    let height = area.height as usize;
+    for row in 0..height {
+        let filled = level.saturating_sub(row * 8).min(8);
+        if filled == 0 {
+            break;
+        }
+        let symbol = match (downwards, filled) {
+            (_, 8) => "█",
+            (false, filled) => EIGHTHS[filled - 1],
+            // Only the upper half block exists among the top aligned blocks
+            (true, filled) if filled >= 4 => "▀",
+            (true, _) => continue,
+        };
+        let y = if downwards {
+            area.y + row as u16
+        } else {
+            area.bottom() - 1 - row as u16
+        };
+        buf[(x, y)]
+            .set_symbol(symbol)
+            .set_fg(level_color((row + 1) as f32 / height as f32));
+    }
+}
+
+impl Widget for Spectrum {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .title(format!("[Spectrum - {}]", self.mode.name()))
+            .title_bottom("[Hide <v> | Mode <m>]")
+            .border_type(BorderType::Rounded)
+            .style(Style::default().fg(Color::Blue));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.width == 0 || inner.height == 0 || self.levels.is_empty() {
+            return;
+        }
+        let (up, down) = match self.mode {
+            SpectrumMode::Mirrored => {
+                let half = inner.height.div_ceil(2);
+                (
+                    Rect {
+                        height: half,
+                        ..inner
+                    },
+                    Some(Rect {
+                        y: inner.y + half,
+                        height: inner.height - half,
+                        ..inner
+                    }),
+                )
+            }
+            SpectrumMode::Bars | SpectrumMode::PeakHold => (inner, None),
+        };
+        for x in 0..inner.width {
+            let level = Self::column(&self.levels, x, inner.width);
+            let column = inner.x + x;
+            draw_bar(
+                buf,
+                up,
+                column,
+                (level * up.height as f32 * 8.).round() as usize,
+                false,
+            );
+            if let Some(down) = down {
+                draw_bar(
+                    buf,
+                    down,
+                    column,
+                    (level * down.height as f32 * 8.).round() as usize,
+                    true,
+                );
+            }
+            if self.mode == SpectrumMode::PeakHold {
+                let peak = Self::column(&self.peaks, x, inner.width);
+                let row = ((peak * up.height as f32).ceil() as u16).min(up.height);
+                if row > 0 {
+                    buf[(column, up.bottom() - row)]
+                        .set_symbol("▔")
+                        .set_fg(Color::White);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    backtrace::Backtrace,
+    fs::OpenOptions,
+    io::Write,
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::ThreadId,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::prelude::CrosstermBackend;
+use signal_hook::{
+    SigId,
+    consts::{SIGINT, SIGTERM},
+};
+
+use crate::error::{Error, Result};
+
+#[doc = "Raw mode terminal on the alternate screen, put back in its original state when dropped"]
+pub struct TerminalGuard {
+    terminal: DefaultTerminal,
+    interrupted: Arc<AtomicBool>,
+    signals: Vec<SigId>,
+}
+
+impl TerminalGuard {
+    pub fn init() -> Result<Self> {
+        UI_THREAD.get_or_init(|| {
+            set_panic_hook();
+            std::thread::current().id()
+        });
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let signals = [SIGINT, SIGTERM]
+            .into_iter()
+            .map(|signal| signal_hook::flag::register(signal, interrupted.clone()))
+            .collect::<std::io::Result<Vec<SigId>>>()
+            .map_err(Error::Terminal)?;
+        let terminal = ratatui::Terminal::new(CrosstermBackend::new(std::io::stdout()))
+            .map_err(Error::Terminal)?;
+        // The guard exists before the terminal is touched, so that its drop restores it on any error below
+        let mut guard = Self {
+            terminal,
+            interrupted,
+            signals,
+        };
+        enable_raw_mode().map_err(Error::Terminal)?;
+        execute!(std::io::stdout(), EnterAlternateScreen, EnableMouseCapture)
+            .map_err(Error::Terminal)?;
+        guard.terminal.clear().map_err(Error::Terminal)?;
+        Ok(guard)
+    }
+    #[doc = "True once SIGINT or SIGTERM was received"]
+    pub fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = DefaultTerminal;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        self.signals.drain(..).for_each(|id| {
+            signal_hook::low_level::unregister(id);
+        });
+        restore();
+    }
+}
+
+fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(std::io::stdout(), DisableMouseCapture, LeaveAlternateScreen);
+}
+
+#[doc = "Where crash reports are appended"]
+pub fn crash_log_path() -> Option<PathBuf> {
+    Some(
+        dirs::data_local_dir()?
+            .join("TUIAudioPlayer")
+            .join("crash.log"),
+    )
+}
+
+#[doc = "Thread that draws the terminal, set with the panic hook by the first guard"]
+static UI_THREAD: OnceLock<ThreadId> = OnceLock::new();
+
+#[doc = "Write the panic message with a backtrace to the crash log, then hand the panic to the previous hook. The terminal is only restored when the UI thread panics, a worker thread panicking leaves the UI running"]
+fn set_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if UI_THREAD.get() == Some(&std::thread::current().id()) {
+            restore();
+        }
+        let report = format!(
+            "[{}] {info}\n{}\n",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+            Backtrace::force_capture()
+        );
+        let written = crash_log_path().filter(|path| {
+            path.parent()
+                .is_some_and(|dir| std::fs::create_dir_all(dir).is_ok())
+                && OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| file.write_all(report.as_bytes()))
+                    .is_ok()
+        });
+        if let Some(path) = written {
+            eprintln!("[x] Crash report written to {}", path.to_string_lossy());
+        }
+        previous(info);
+    }));
+}
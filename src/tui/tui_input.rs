@@ -1,29 +1,51 @@
-use std::time::Duration;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use lofty::tag::Accessor;
-use ratatui::crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::event::{
+    KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::{Margin, Position, Rect};
+use ratatui::widgets::ListState;
 
-use crate::audio::{AudioPlayer, AudioSource};
+use crate::audio::{
+    AudioPlayer, AudioSource, DeviceSelector, EqPreset, EqSettings, Track, expand_cue_sheets,
+    is_cue_sheet, read_cue_sheet, verify_audio_file,
+};
+use crate::bookmarks::{Bookmark, Bookmarks};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::playlist::is_playlist;
 
 use super::{
-    AppStatePlay, PlayerSelection,
-    utils::{self, get_sample_rate, verify_path_extension},
+    AppStatePlay, AppStatePlayer, BookmarkList, DevicePicker, EqPanel, FilterPanel,
+    PlayerSelection, PlaylistPrompt,
+    explorer::{FileExplorer, Input},
+    utils,
 };
 
 pub fn handle_play_event(
     audio_player: &mut AudioPlayer,
-    running: &mut bool,
-) -> Result<(), std::io::Error> {
-    if ratatui::crossterm::event::poll(Duration::from_millis(0))? {
-        let event = ratatui::crossterm::event::read()?;
+    app_state_play: &mut AppStatePlay,
+    config: &Config,
+) -> Result<()> {
+    if ratatui::crossterm::event::poll(Duration::from_millis(0)).map_err(Error::Terminal)? {
+        let event = ratatui::crossterm::event::read().map_err(Error::Terminal)?;
         match event {
             ratatui::crossterm::event::Event::FocusGained => {}
             ratatui::crossterm::event::Event::FocusLost => {}
             ratatui::crossterm::event::Event::Key(key_event) => {
-                (key_event.code == KeyCode::Char('q')).then(|| *running = false);
-                play_key_input(audio_player, key_event);
+                (key_event.code == KeyCode::Char('q') && !app_state_play.prompt_open())
+                    .then(|| app_state_play.stop());
+                is_interrupt(&event).then(|| app_state_play.stop());
+                play_key_input(audio_player, app_state_play, key_event, config)?;
+            }
+            ratatui::crossterm::event::Event::Mouse(mouse_event) => {
+                let (width, height) =
+                    ratatui::crossterm::terminal::size().map_err(Error::Terminal)?;
+                play_mouse_input(audio_player, mouse_event, Rect::new(0, 0, width, height));
             }
-            ratatui::crossterm::event::Event::Mouse(_mouse_event) => {}
             ratatui::crossterm::event::Event::Paste(_) => {}
             ratatui::crossterm::event::Event::Resize(_, _) => {}
         }
@@ -31,10 +53,82 @@ pub fn handle_play_event(
     Ok(())
 }
 
+#[doc = "Clicking or dragging on the progress gauge seeks, scrolling changes the volume"]
+pub fn play_mouse_input(audio_player: &mut AudioPlayer, mouse_event: MouseEvent, area: Rect) {
+    let position = Position::new(mouse_event.column, mouse_event.row);
+    if !area.contains(position) {
+        return;
+    }
+    let gauge = AppStatePlay::gauge_area(area);
+    match mouse_event.kind {
+        MouseEventKind::Down(MouseButton::Left) if gauge.contains(position) => {
+            audio_player.seek_fraction((position.x - gauge.x) as f64 / gauge.width as f64)
+        }
+        MouseEventKind::Drag(MouseButton::Left) if gauge.width > 0 => audio_player
+            .seek_fraction(position.x.saturating_sub(gauge.x) as f64 / gauge.width as f64),
+        MouseEventKind::ScrollUp => audio_player.higher_volume(),
+        MouseEventKind::ScrollDown => audio_player.lower_volume(),
+        _ => {}
+    }
+}
+
+#[doc = "Ctrl+C, raw mode turns it into a key press instead of a SIGINT"]
+fn is_interrupt(event: &ratatui::crossterm::event::Event) -> bool {
+    matches!(event, ratatui::crossterm::event::Event::Key(key_event)
+        if key_event.code == KeyCode::Char('c')
+            && key_event.kind == KeyEventKind::Press
+            && key_event.modifiers == KeyModifiers::CONTROL)
+}
+
+#[doc = "Record a left click and tell if it completes a double click on the same row"]
+fn is_double_click(last_click: &mut Option<(Instant, u16)>, row: u16) -> bool {
+    let double = last_click
+        .is_some_and(|(at, last_row)| last_row == row && at.elapsed() < Duration::from_millis(400));
+    *last_click = (!double).then(|| (Instant::now(), row));
+    double
+}
+
 pub fn play_key_input(
     audio_player: &mut AudioPlayer,
+    app_state_play: &mut AppStatePlay,
     key_event: ratatui::crossterm::event::KeyEvent,
-) {
+    config: &Config,
+) -> Result<()> {
+    if app_state_play.goto.is_some() {
+        goto_key_input(audio_player, &mut app_state_play.goto, key_event);
+        return Ok(());
+    }
+    if app_state_play.help {
+        (matches!(key_event.code, KeyCode::Char('?') | KeyCode::Esc)
+            && key_event.kind == KeyEventKind::Press)
+            .then(|| app_state_play.help = false);
+        return Ok(());
+    }
+    if app_state_play.device_picker.is_some() {
+        return device_key_input(
+            audio_player,
+            &mut app_state_play.device_picker,
+            key_event,
+            app_state_play.debug,
+        );
+    }
+    if app_state_play.eq_panel.is_some() {
+        return eq_key_input(audio_player, &mut app_state_play.eq_panel, key_event);
+    }
+    if app_state_play.filter_panel.is_some() {
+        filter_key_input(audio_player, &mut app_state_play.filter_panel, key_event);
+        return Ok(());
+    }
+    if app_state_play.bookmark_prompt.is_some() {
+        return bookmark_prompt_key_input(
+            &mut app_state_play.bookmark_prompt,
+            &app_state_play.track,
+            key_event,
+        );
+    }
+    if app_state_play.bookmark_list.is_some() {
+        return bookmark_list_key_input(audio_player, &mut app_state_play.bookmark_list, key_event);
+    }
     (key_event.code == KeyCode::Enter && key_event.kind == KeyEventKind::Press).then(|| {
         if audio_player.is_paused() {
             audio_player.play()
@@ -42,10 +136,90 @@ pub fn play_key_input(
             audio_player.pause();
         }
     });
-    (key_event.code == KeyCode::Right && key_event.kind == KeyEventKind::Press)
-        .then(|| audio_player.fast_forward());
-    (key_event.code == KeyCode::Left && key_event.kind == KeyEventKind::Press)
+    (key_event.code == KeyCode::Right
+        && key_event.kind == KeyEventKind::Press
+        && key_event.modifiers == KeyModifiers::empty())
+    .then(|| audio_player.seek_relative(config.seek_step as i64));
+    (key_event.code == KeyCode::Left
+        && key_event.kind == KeyEventKind::Press
+        && key_event.modifiers == KeyModifiers::empty())
+    .then(|| audio_player.seek_relative(-(config.seek_step as i64)));
+    (key_event.code == KeyCode::Right
+        && key_event.kind == KeyEventKind::Press
+        && key_event.modifiers == KeyModifiers::SHIFT)
+        .then(|| audio_player.seek_relative(config.seek_step_large as i64));
+    (key_event.code == KeyCode::Left
+        && key_event.kind == KeyEventKind::Press
+        && key_event.modifiers == KeyModifiers::SHIFT)
+        .then(|| audio_player.seek_relative(-(config.seek_step_large as i64)));
+    (key_event.code == KeyCode::Home && key_event.kind == KeyEventKind::Press)
         .then(|| audio_player.rewind());
+    (key_event.code == KeyCode::Char('g') && key_event.kind == KeyEventKind::Press)
+        .then(|| app_state_play.goto = Some(String::new()));
+    (key_event.code == KeyCode::Char('?') && key_event.kind == KeyEventKind::Press)
+        .then(|| app_state_play.help = true);
+    (key_event.code == KeyCode::Char('e') && key_event.kind == KeyEventKind::Press).then(|| {
+        app_state_play.eq_panel = Some(EqPanel {
+            settings: audio_player.equalizer_settings(),
+            selected: 0,
+        })
+    });
+    (key_event.code == KeyCode::Char('f') && key_event.kind == KeyEventKind::Press).then(|| {
+        app_state_play.filter_panel = Some(FilterPanel {
+            settings: audio_player.filter_settings(),
+            selected: 0,
+        })
+    });
+    if key_event.code == KeyCode::Char('r') && key_event.kind == KeyEventKind::Press {
+        let mut replay_gain = audio_player.replay_gain();
+        replay_gain.mode = replay_gain.mode.next();
+        audio_player.set_replay_gain(replay_gain);
+    }
+    (key_event.code == KeyCode::Char('R') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_repeat(audio_player.repeat().next()));
+    (key_event.code == KeyCode::Char('s') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_shuffle(audio_player.shuffle().next()));
+    (key_event.code == KeyCode::Char('v') && key_event.kind == KeyEventKind::Press)
+        .then(|| app_state_play.spectrum.toggle());
+    (key_event.code == KeyCode::Char('m') && key_event.kind == KeyEventKind::Press)
+        .then(|| app_state_play.spectrum.mode = app_state_play.spectrum.mode.next());
+    (key_event.code == KeyCode::Char('l') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_limiter(!audio_player.is_limiting()));
+    (key_event.code == KeyCode::Char('k') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.reset_clip());
+    (key_event.code == KeyCode::Char('+') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_semitones(audio_player.semitones() + 1));
+    (key_event.code == KeyCode::Char('-') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_semitones(audio_player.semitones() - 1));
+    (key_event.code == KeyCode::Char('t') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_preserve_pitch(!audio_player.preserve_pitch()));
+    (key_event.code == KeyCode::Char('[') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_loop_a());
+    (key_event.code == KeyCode::Char(']') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.set_loop_b());
+    (key_event.code == KeyCode::Char('\\') && key_event.kind == KeyEventKind::Press)
+        .then(|| audio_player.clear_loop());
+    (key_event.code == KeyCode::Char('b') && key_event.kind == KeyEventKind::Press).then(|| {
+        app_state_play.bookmark_prompt = Some((audio_player.file_position(), String::new()))
+    });
+    if key_event.code == KeyCode::Char('B') && key_event.kind == KeyEventKind::Press {
+        app_state_play.bookmark_list = Some(BookmarkList::open(&app_state_play.track)?);
+    }
+    (key_event.code == KeyCode::Char('o') && key_event.kind == KeyEventKind::Press).then(|| {
+        app_state_play.device_picker = Some(DevicePicker::open(audio_player.device_name()))
+    });
+    if let KeyCode::Char(digit @ '0'..='9') = key_event.code
+        && key_event.kind == KeyEventKind::Press
+    {
+        let tenths = digit.to_digit(10).unwrap_or_default();
+        audio_player.seek_fraction(tenths as f64 / 10.);
+    }
+    if key_event.code == KeyCode::Char('n') && key_event.kind == KeyEventKind::Press {
+        audio_player.next_track()?;
+    }
+    if key_event.code == KeyCode::Char('p') && key_event.kind == KeyEventKind::Press {
+        audio_player.previous_track()?;
+    }
     (key_event.code == KeyCode::Up
         && key_event.kind == KeyEventKind::Press
         && key_event.modifiers == KeyModifiers::empty())
@@ -62,22 +236,386 @@ pub fn play_key_input(
         && key_event.kind == KeyEventKind::Press
         && key_event.modifiers == KeyModifiers::SHIFT)
         .then(|| audio_player.slower_playback());
+    Ok(())
+}
+
+#[doc = "Wait for a key press or a click to close the error popup, Ctrl+C still quits and other events are dropped"]
+pub fn dismiss_error(error: &mut Option<String>, running: &mut bool) -> Result<()> {
+    if ratatui::crossterm::event::poll(Duration::from_millis(0)).map_err(Error::Terminal)? {
+        let event = ratatui::crossterm::event::read().map_err(Error::Terminal)?;
+        if is_interrupt(&event) {
+            *running = false;
+            return Ok(());
+        }
+        match event {
+            ratatui::crossterm::event::Event::Key(key_event)
+                if key_event.kind == KeyEventKind::Press =>
+            {
+                *error = None
+            }
+            ratatui::crossterm::event::Event::Mouse(mouse_event)
+                if matches!(mouse_event.kind, MouseEventKind::Down(_)) =>
+            {
+                *error = None
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[doc = "Edit the go to time prompt, Enter seeks to the typed time and Esc closes it"]
+fn goto_key_input(
+    audio_player: &mut AudioPlayer,
+    goto: &mut Option<String>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) {
+    if key_event.kind != KeyEventKind::Press {
+        return;
+    }
+    match key_event.code {
+        KeyCode::Char(c) if c.is_ascii_digit() || c == ':' => {
+            if let Some(input) = goto.as_mut() {
+                input.push(c);
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some(input) = goto.as_mut() {
+                input.pop();
+            }
+        }
+        KeyCode::Enter => {
+            if let Some(position) = goto.take().and_then(|input| utils::parse_timestamp(&input)) {
+                audio_player.seek_to(position);
+            }
+        }
+        KeyCode::Esc => *goto = None,
+        _ => {}
+    }
+}
+
+#[doc = "Type the name of a new bookmark, Enter saves it at the position <b> was pressed at"]
+fn bookmark_prompt_key_input(
+    bookmark_prompt: &mut Option<(Duration, String)>,
+    track: &Path,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    match key_event.code {
+        KeyCode::Char(c) => {
+            if let Some((_, name)) = bookmark_prompt.as_mut() {
+                name.push(c);
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some((_, name)) = bookmark_prompt.as_mut() {
+                name.pop();
+            }
+        }
+        KeyCode::Enter => {
+            if let Some((position, name)) = bookmark_prompt.take() {
+                let name = match name.trim() {
+                    "" => utils::format_timestamp(position),
+                    name => name.to_string(),
+                };
+                Bookmarks::persist(|bookmarks| {
+                    bookmarks.add(
+                        track,
+                        Bookmark {
+                            name,
+                            position: position.as_secs_f64(),
+                        },
+                    )
+                })?;
+            }
+        }
+        KeyCode::Esc => *bookmark_prompt = None,
+        _ => {}
+    }
+    Ok(())
 }
 
+#[doc = "Move in the bookmarks of the track, Enter jumps to the highlighted one and <d> deletes it"]
+fn bookmark_list_key_input(
+    audio_player: &mut AudioPlayer,
+    bookmark_list: &mut Option<BookmarkList>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let Some(list) = bookmark_list.as_mut() else {
+        return Ok(());
+    };
+    let selected = list
+        .state
+        .selected()
+        .and_then(|selected| list.bookmarks.get(selected).cloned());
+    match key_event.code {
+        KeyCode::Up => list.state.select_previous(),
+        KeyCode::Down
+            if list
+                .state
+                .selected()
+                .is_some_and(|selected| selected + 1 < list.bookmarks.len()) =>
+        {
+            list.state.select_next()
+        }
+        KeyCode::Enter => {
+            *bookmark_list = None;
+            if let Some(bookmark) = selected {
+                audio_player.seek_file_position(bookmark.position());
+            }
+        }
+        KeyCode::Char('d') | KeyCode::Delete => {
+            if let Some(bookmark) = selected {
+                Bookmarks::persist(|bookmarks| bookmarks.remove(&list.track, &bookmark))?;
+                list.bookmarks.retain(|other| *other != bookmark);
+                let last = list.bookmarks.len().checked_sub(1);
+                list.state.select(
+                    list.state
+                        .selected()
+                        .zip(last)
+                        .map(|(index, last)| index.min(last)),
+                );
+            }
+        }
+        KeyCode::Char('B') | KeyCode::Esc => *bookmark_list = None,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[doc = "Move in the device picker, Enter switches to the highlighted device and Esc closes it"]
+fn device_key_input(
+    audio_player: &mut AudioPlayer,
+    device_picker: &mut Option<DevicePicker>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+    debug: bool,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let Some(picker) = device_picker.as_mut() else {
+        return Ok(());
+    };
+    match key_event.code {
+        KeyCode::Up => picker.state.select_previous(),
+        KeyCode::Down
+            if picker
+                .state
+                .selected()
+                .is_some_and(|selected| selected + 1 < picker.devices.len()) =>
+        {
+            picker.state.select_next()
+        }
+        KeyCode::Enter => {
+            let device = picker
+                .state
+                .selected()
+                .and_then(|selected| picker.devices.get(selected).cloned());
+            *device_picker = None;
+            if let Some(device) = device {
+                audio_player.switch_device(device, debug)?;
+            }
+        }
+        KeyCode::Esc => *device_picker = None,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[doc = "Edit the equalizer live, the settings are saved to the config when the panel closes"]
+fn eq_key_input(
+    audio_player: &mut AudioPlayer,
+    eq_panel: &mut Option<EqPanel>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let Some(panel) = eq_panel.as_mut() else {
+        return Ok(());
+    };
+    let bands = panel.settings.bands.len();
+    match key_event.code {
+        KeyCode::Left => panel.selected = panel.selected.saturating_sub(1),
+        KeyCode::Right => panel.selected = (panel.selected + 1).min(bands.saturating_sub(1)),
+        KeyCode::Up => panel.settings.adjust_gain(panel.selected, 1.),
+        KeyCode::Down => panel.settings.adjust_gain(panel.selected, -1.),
+        KeyCode::Enter => panel.settings.enabled = !panel.settings.enabled,
+        KeyCode::Char('p') => {
+            let preset = panel.settings.preset().map_or(EqPreset::Flat, |p| p.next());
+            panel.settings = EqSettings {
+                enabled: panel.settings.enabled,
+                ..EqSettings::from_preset(preset)
+            };
+        }
+        KeyCode::Char('e') | KeyCode::Esc => {
+            let settings = panel.settings.clone();
+            *eq_panel = None;
+            return Config::persist(|config| config.equalizer = settings);
+        }
+        _ => return Ok(()),
+    }
+    audio_player.set_equalizer(panel.settings.clone());
+    Ok(())
+}
+
+#[doc = "Toggle the filters and move their cutoff by a sixth of an octave, or an octave with Shift"]
+fn filter_key_input(
+    audio_player: &mut AudioPlayer,
+    filter_panel: &mut Option<FilterPanel>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) {
+    if key_event.kind != KeyEventKind::Press {
+        return;
+    }
+    let Some(panel) = filter_panel.as_mut() else {
+        return;
+    };
+    let step = if key_event.modifiers == KeyModifiers::SHIFT {
+        2.
+    } else {
+        2f32.powf(1. / 6.)
+    };
+    let filter = if panel.selected == 0 {
+        &mut panel.settings.low_pass
+    } else {
+        &mut panel.settings.high_pass
+    };
+    match key_event.code {
+        KeyCode::Up => panel.selected = 0,
+        KeyCode::Down => panel.selected = 1,
+        KeyCode::Left => filter.scale(1. / step),
+        KeyCode::Right => filter.scale(step),
+        KeyCode::Enter => filter.enabled = !filter.enabled,
+        KeyCode::Char('f') | KeyCode::Esc => {
+            *filter_panel = None;
+            return;
+        }
+        _ => return,
+    }
+    audio_player.set_filters(panel.settings);
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn handle_player_event(
     running: &mut bool,
-    file_explorer: &mut ratatui_explorer::FileExplorer,
+    file_explorer: &mut FileExplorer,
     which: &mut PlayerSelection,
     audio_source: &mut Option<AudioSource>,
     app_player: &mut Option<AudioPlayer>,
     app_state_play: &mut AppStatePlay,
+    queue_state: &mut ListState,
+    last_click: &mut Option<(Instant, u16)>,
+    playlist_prompt: &mut Option<PlaylistPrompt>,
+    config: &Config,
     debug: bool,
-) -> Result<(), std::io::Error> {
-    if ratatui::crossterm::event::poll(Duration::from_millis(0))? {
-        let event = ratatui::crossterm::event::read()?;
-        //
+) -> Result<()> {
+    if ratatui::crossterm::event::poll(Duration::from_millis(0)).map_err(Error::Terminal)? {
+        let event = ratatui::crossterm::event::read().map_err(Error::Terminal)?;
+        if is_interrupt(&event) {
+            debug.then(|| println!("[?] Quitting player"));
+            *running = false;
+            return Ok(());
+        }
+        if let ratatui::crossterm::event::Event::Mouse(mouse_event) = event {
+            let (width, height) = ratatui::crossterm::terminal::size().map_err(Error::Terminal)?;
+            let [explorer, queue, play] =
+                AppStatePlayer::layout(Rect::new(0, 0, width, height), audio_source.is_some());
+            let position = Position::new(mouse_event.column, mouse_event.row);
+            let clicked = mouse_event.kind == MouseEventKind::Down(MouseButton::Left);
+            let scroll = match mouse_event.kind {
+                MouseEventKind::ScrollUp => Some(Input::Up),
+                MouseEventKind::ScrollDown => Some(Input::Down),
+                _ => None,
+            };
+            if explorer.contains(position) {
+                let inner = explorer.inner(Margin {
+                    horizontal: 1,
+                    vertical: 1,
+                });
+                if let Some(input) = scroll {
+                    file_explorer.handle(input)?;
+                } else if clicked && inner.contains(position) {
+                    *which = PlayerSelection::FileExplorer;
+                    // The explorer renders with a fresh list state, scrolled just enough to show the selection
+                    let first = file_explorer
+                        .selected_idx()
+                        .saturating_sub((inner.height as usize).saturating_sub(1));
+                    let index = first + (position.y - inner.y) as usize;
+                    if index < file_explorer.len() {
+                        file_explorer.set_selected_idx(index);
+                        if is_double_click(last_click, position.y) {
+                            if file_explorer.current().is_some_and(|entry| entry.is_dir()) {
+                                file_explorer.handle(Input::Right)?;
+                            } else {
+                                play_selected(
+                                    file_explorer,
+                                    audio_source,
+                                    app_player,
+                                    app_state_play,
+                                    config,
+                                    debug,
+                                )?;
+                                *which = PlayerSelection::AudioPlayer;
+                            }
+                        }
+                    }
+                }
+            } else if let Some(audio_player) = app_player {
+                if queue.contains(position) {
+                    let inner = queue.inner(Margin {
+                        horizontal: 1,
+                        vertical: 1,
+                    });
+                    match scroll {
+                        Some(Input::Up) => queue_state.select_previous(),
+                        Some(_) => queue_state.select_next(),
+                        None if clicked && inner.contains(position) => {
+                            *which = PlayerSelection::Queue;
+                            let index = queue_state.offset() + (position.y - inner.y) as usize;
+                            if index < audio_player.get_queue().tracks().len() {
+                                queue_state.select(Some(index));
+                                if is_double_click(last_click, position.y) {
+                                    audio_player.jump_to(index)?;
+                                }
+                            }
+                        }
+                        None => {}
+                    }
+                } else if play.contains(position) {
+                    clicked.then(|| *which = PlayerSelection::AudioPlayer);
+                    play_mouse_input(audio_player, mouse_event, play);
+                }
+            }
+            return Ok(());
+        }
         match which {
             PlayerSelection::FileExplorer => {
+                if let (ratatui::crossterm::event::Event::Key(key_event), true) =
+                    (&event, file_explorer.sort_menu_open())
+                {
+                    file_explorer.sort_menu_key(*key_event);
+                    return Ok(());
+                }
+                if let (ratatui::crossterm::event::Event::Key(key_event), true) =
+                    (&event, file_explorer.searching())
+                {
+                    return search_key_input(
+                        file_explorer,
+                        which,
+                        audio_source,
+                        app_player,
+                        app_state_play,
+                        *key_event,
+                        config,
+                        debug,
+                    );
+                }
                 match event {
                     ratatui::crossterm::event::Event::FocusGained => {}
                     ratatui::crossterm::event::Event::FocusLost => {}
@@ -91,7 +629,6 @@ pub fn handle_player_event(
                                 *audio_source = None;
                                 *app_player = None;
                                 *app_state_play = AppStatePlay::default();
-                                return;
                             } else {
                                 debug.then(|| println!("[?] Quitting player"));
                                 *running = false
@@ -103,45 +640,55 @@ pub fn handle_player_event(
                             && audio_source.is_some())
                         .then(|| {
                             debug.then(|| println!("[?]Switching tab"));
-                            which.toggle()
+                            which.cycle()
                         });
 
-                        (key_event.code == KeyCode::Enter
+                        // Enter on a directory is left to the explorer which opens it
+                        if key_event.code == KeyCode::Enter
+                            && key_event.kind == KeyEventKind::Press
+                            && key_event.modifiers == KeyModifiers::empty()
+                            && file_explorer.current().is_some_and(|entry| !entry.is_dir())
+                        {
+                            play_selected(
+                                file_explorer,
+                                audio_source,
+                                app_player,
+                                app_state_play,
+                                config,
+                                debug,
+                            )?;
+                            *which = PlayerSelection::AudioPlayer;
+                        }
+
+                        (key_event.code == KeyCode::Char('f')
                             && key_event.kind == KeyEventKind::Press
                             && key_event.modifiers == KeyModifiers::empty())
-                        .then(|| {
-                            which.toggle();
-                            let path = verify_path_extension(file_explorer.current().path())
-                                .expect("[x] App: Invalid file selected");
-                            *audio_source = Some(AudioSource::from(path.clone()));
-                            debug.then(|| {
-                                audio_source
-                                    .is_some()
-                                    .then(|| println!("[+] AudioSource created"));
-                                audio_source
-                                    .is_none()
-                                    .then(|| println!("[x] Failed to create audio source"));
-                                println!("[?] Valid Audio Source created")
-                            });
-                            if let Some(audio) = audio_source {
-                                let tag = utils::get_tags(&path);
-                                match audio.play(None, None, debug) {
-                                    Ok(player) => {
-                                        *app_player = Some(player);
-                                        app_state_play
-                                            .set_full_title(audio.get_title())
-                                            .set_filename(tag.title().unwrap_or_default())
-                                            .set_artist(tag.artist().unwrap_or_default())
-                                            .set_album(tag.album().unwrap_or_default())
-                                            .set_genre(tag.genre().unwrap_or_default())
-                                            .set_sample_rate(get_sample_rate(&path))
-                                            .set_total_duration(utils::get_total_duration(&path))
-                                            .set_debug(debug);
-                                    }
-                                    Err(e) => panic!("{e}"),
-                                }
-                            }
-                        });
+                        .then(|| file_explorer.toggle_audio_only());
+                        (key_event.code == KeyCode::Char('o')
+                            && key_event.kind == KeyEventKind::Press
+                            && key_event.modifiers == KeyModifiers::empty())
+                        .then(|| file_explorer.open_sort_menu());
+
+                        if let Some(selected) = file_explorer
+                            .current()
+                            .filter(|entry| entry.name() != "../")
+                            .map(|entry| entry.path())
+                            && key_event.code == KeyCode::Char('a')
+                            && key_event.kind == KeyEventKind::Press
+                            && key_event.modifiers == KeyModifiers::empty()
+                        {
+                            enqueue_selected(
+                                selected,
+                                audio_source,
+                                app_player,
+                                app_state_play,
+                                config,
+                                debug,
+                            )?;
+                        }
+                        (key_event.code == KeyCode::Char('/')
+                            && key_event.kind == KeyEventKind::Press)
+                            .then(|| file_explorer.open_search());
                     }
                     ratatui::crossterm::event::Event::Mouse(_mouse_event) => {}
                     ratatui::crossterm::event::Event::Paste(_) => {}
@@ -149,40 +696,55 @@ pub fn handle_player_event(
                 }
                 file_explorer.handle(&event)?
             }
-            PlayerSelection::AudioPlayer => {
-                audio_source.is_none().then(|| which.toggle());
+            PlayerSelection::AudioPlayer | PlayerSelection::Queue => {
+                audio_source
+                    .is_none()
+                    .then(|| *which = PlayerSelection::FileExplorer);
 
-                match event {
-                    ratatui::crossterm::event::Event::Key(key_event) => {
-                        (key_event.code == KeyCode::Char('q')
-                            && key_event.kind == KeyEventKind::Press
-                            && key_event.modifiers == KeyModifiers::empty())
+                if let ratatui::crossterm::event::Event::Key(key_event) = event {
+                    if let (Some(_), Some(audio_player)) = (&playlist_prompt, &app_player) {
+                        return playlist_prompt_key_input(
+                            playlist_prompt,
+                            audio_player.get_queue().tracks(),
+                            file_explorer.cwd(),
+                            key_event,
+                        );
+                    }
+                    let prompt_open = app_state_play.prompt_open();
+                    (key_event.code == KeyCode::Char('q')
+                        && key_event.kind == KeyEventKind::Press
+                        && key_event.modifiers == KeyModifiers::empty()
+                        && !prompt_open)
                         .then(|| {
                             if audio_source.is_some() {
                                 debug.then(|| println!("[?] Removing audio from player"));
                                 *audio_source = None;
                                 *app_player = None;
                                 *app_state_play = AppStatePlay::default();
-                                return;
                             } else {
                                 debug.then(|| println!("[?] Quitting player"));
                                 *running = false
                             }
                         });
 
-                        (key_event.code == KeyCode::Tab
-                            && key_event.kind == KeyEventKind::Press
-                            && key_event.modifiers == KeyModifiers::empty())
+                    (key_event.code == KeyCode::Tab
+                        && key_event.kind == KeyEventKind::Press
+                        && key_event.modifiers == KeyModifiers::empty()
+                        && !prompt_open)
                         .then(|| {
                             debug.then(|| println!("[?]Switching tab"));
-                            which.toggle()
+                            which.cycle()
                         });
 
-                        if let Some(audio_player) = app_player {
-                            play_key_input(audio_player, key_event);
+                    match (which, app_player) {
+                        (PlayerSelection::AudioPlayer, Some(audio_player)) => {
+                            play_key_input(audio_player, app_state_play, key_event, config)?
+                        }
+                        (PlayerSelection::Queue, Some(audio_player)) => {
+                            queue_key_input(audio_player, queue_state, playlist_prompt, key_event)?
                         }
+                        _ => {}
                     }
-                    _ => {}
                 }
             }
         }
@@ -190,3 +752,328 @@ pub fn handle_player_event(
 
     Ok(())
 }
+
+pub fn queue_key_input(
+    audio_player: &mut AudioPlayer,
+    queue_state: &mut ListState,
+    playlist_prompt: &mut Option<PlaylistPrompt>,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let len = audio_player.get_queue().tracks().len();
+    let Some(selected) = queue_state.selected() else {
+        return Ok(());
+    };
+    match (key_event.code, key_event.modifiers) {
+        (KeyCode::Up, KeyModifiers::NONE) => queue_state.select_previous(),
+        (KeyCode::Down, KeyModifiers::NONE) => queue_state.select_next(),
+        (KeyCode::Up, KeyModifiers::SHIFT) if selected > 0 => {
+            audio_player
+                .move_track(selected, selected - 1)
+                .then(|| queue_state.select_previous());
+        }
+        (KeyCode::Down, KeyModifiers::SHIFT) => {
+            audio_player
+                .move_track(selected, selected + 1)
+                .then(|| queue_state.select_next());
+        }
+        (KeyCode::Enter, KeyModifiers::NONE) => {
+            audio_player.jump_to(selected)?;
+        }
+        (KeyCode::Char('d') | KeyCode::Delete, KeyModifiers::NONE) => {
+            audio_player.remove_track(selected)?;
+            (selected + 1 >= len).then(|| queue_state.select_previous());
+        }
+        (KeyCode::Char('w'), KeyModifiers::NONE) => {
+            *playlist_prompt = Some(PlaylistPrompt {
+                name: "queue.m3u8".to_string(),
+                overwrite: false,
+            });
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[doc = "Save the queue as the playlist file typed in the prompt, relative names are resolved against the explorer directory"]
+fn playlist_prompt_key_input(
+    playlist_prompt: &mut Option<PlaylistPrompt>,
+    tracks: &[Track],
+    cwd: &Path,
+    key_event: ratatui::crossterm::event::KeyEvent,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let Some(prompt) = playlist_prompt.as_mut() else {
+        return Ok(());
+    };
+    match key_event.code {
+        KeyCode::Char(c) => {
+            prompt.name.push(c);
+            prompt.overwrite = false;
+        }
+        KeyCode::Backspace => {
+            prompt.name.pop();
+            prompt.overwrite = false;
+        }
+        KeyCode::Enter if !prompt.name.trim().is_empty() => {
+            let path = cwd.join(prompt.name.trim());
+            // An existing file is only replaced after a second Enter
+            if path.exists() && !prompt.overwrite {
+                prompt.overwrite = true;
+                return Ok(());
+            }
+            *playlist_prompt = None;
+            utils::save_playlist(&path, tracks)?;
+        }
+        KeyCode::Enter => *playlist_prompt = None,
+        KeyCode::Esc => *playlist_prompt = None,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[doc = "Enqueue `selected`, every audio file below it when it is a directory, or start playing it when nothing plays"]
+fn enqueue_selected(
+    selected: &Path,
+    audio_source: &mut Option<AudioSource>,
+    app_player: &mut Option<AudioPlayer>,
+    app_state_play: &mut AppStatePlay,
+    config: &Config,
+    debug: bool,
+) -> Result<()> {
+    let (tracks, missing) = if selected.is_dir() {
+        (
+            expand_cue_sheets(utils::list_audio_files(selected, true)),
+            0,
+        )
+    } else if is_playlist(selected) {
+        let (tracks, missing) = utils::read_playlist(selected)?;
+        (tracks, missing.len())
+    } else if is_cue_sheet(selected) {
+        (read_cue_sheet(selected)?, 0)
+    } else {
+        let track =
+            verify_audio_file(selected).ok_or(Error::UnsupportedFormat(selected.to_path_buf()))?;
+        (expand_cue_sheets(vec![track]), 0)
+    };
+    debug.then(|| println!("[?] Enqueuing {} tracks", tracks.len()));
+    match app_player {
+        Some(player) => {
+            for track in tracks {
+                player.enqueue(track)?;
+            }
+        }
+        None => play_queue(
+            tracks,
+            0,
+            audio_source,
+            app_player,
+            app_state_play,
+            config,
+            debug,
+        )?,
+    }
+    match missing {
+        0 => Ok(()),
+        count => Err(Error::MissingEntries(selected.to_path_buf(), count)),
+    }
+}
+
+#[doc = "Keys of the explorer search, Enter plays the selected result and jumps to it, Ctrl+a enqueues it"]
+#[allow(clippy::too_many_arguments)]
+fn search_key_input(
+    file_explorer: &mut FileExplorer,
+    which: &mut PlayerSelection,
+    audio_source: &mut Option<AudioSource>,
+    app_player: &mut Option<AudioPlayer>,
+    app_state_play: &mut AppStatePlay,
+    key_event: ratatui::crossterm::event::KeyEvent,
+    config: &Config,
+    debug: bool,
+) -> Result<()> {
+    if key_event.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    let selected = file_explorer.current().cloned();
+    match (key_event.code, selected) {
+        (KeyCode::Enter, Some(selected)) => {
+            if !selected.is_dir() {
+                play_selected(
+                    file_explorer,
+                    audio_source,
+                    app_player,
+                    app_state_play,
+                    config,
+                    debug,
+                )?;
+                *which = PlayerSelection::AudioPlayer;
+            }
+            file_explorer.jump_to_result()
+        }
+        (KeyCode::Char('a'), Some(selected))
+            if key_event.modifiers.contains(KeyModifiers::CONTROL) =>
+        {
+            enqueue_selected(
+                selected.path(),
+                audio_source,
+                app_player,
+                app_state_play,
+                config,
+                debug,
+            )
+        }
+        _ => {
+            file_explorer.search_key(key_event);
+            Ok(())
+        }
+    }
+}
+
+#[doc = "Play the file highlighted in the explorer, queuing the rest of its directory after it, or the tracks of a playlist or of a CUE sheet"]
+fn play_selected(
+    file_explorer: &FileExplorer,
+    audio_source: &mut Option<AudioSource>,
+    app_player: &mut Option<AudioPlayer>,
+    app_state_play: &mut AppStatePlay,
+    config: &Config,
+    debug: bool,
+) -> Result<()> {
+    let Some(selected) = file_explorer.current().map(|entry| entry.path()) else {
+        return Ok(());
+    };
+    if is_playlist(selected) {
+        let (tracks, missing) = utils::read_playlist(selected)?;
+        play_queue(
+            tracks,
+            0,
+            audio_source,
+            app_player,
+            app_state_play,
+            config,
+            debug,
+        )?;
+        return match missing.len() {
+            0 => Ok(()),
+            count => Err(Error::MissingEntries(selected.clone(), count)),
+        };
+    }
+    if is_cue_sheet(selected) {
+        let tracks = read_cue_sheet(selected)?;
+        if tracks.is_empty() {
+            return Err(Error::EmptyPlaylist(selected.clone()));
+        }
+        return play_queue(
+            tracks,
+            0,
+            audio_source,
+            app_player,
+            app_state_play,
+            config,
+            debug,
+        );
+    }
+    let path = verify_audio_file(selected).ok_or(Error::UnsupportedFormat(selected.clone()))?;
+    // Files described by a CUE sheet are queued as its tracks, starting with the first one
+    let tracks = expand_cue_sheets(
+        path.parent()
+            .map(|dir| utils::list_audio_files(dir, false))
+            .unwrap_or_default(),
+    );
+    let (tracks, start) = match tracks.iter().position(|track| track.path == path) {
+        Some(start) => (tracks, start),
+        None => (expand_cue_sheets(vec![path]), 0),
+    };
+    play_queue(
+        tracks,
+        start,
+        audio_source,
+        app_player,
+        app_state_play,
+        config,
+        debug,
+    )
+}
+
+#[doc = "Start a new player on `tracks`, beginning with the track at index `start`"]
+pub fn play_queue(
+    tracks: Vec<Track>,
+    start: usize,
+    audio_source: &mut Option<AudioSource>,
+    app_player: &mut Option<AudioPlayer>,
+    app_state_play: &mut AppStatePlay,
+    config: &Config,
+    debug: bool,
+) -> Result<()> {
+    let Some(track) = tracks.get(start).cloned() else {
+        return Ok(());
+    };
+    // Settings changed live on the previous player carry over to the new one
+    let (
+        device,
+        filters,
+        equalizer,
+        replay_gain,
+        limiting,
+        (semitones, preserve_pitch),
+        (volume, speed),
+    ) = match app_player {
+        Some(previous) => (
+            Some(previous.device_name().to_string()).filter(|name| !name.is_empty()),
+            previous.filter_settings(),
+            previous.equalizer_settings(),
+            previous.replay_gain(),
+            previous.is_limiting(),
+            (previous.semitones(), previous.preserve_pitch()),
+            (previous.volume(), previous.speed()),
+        ),
+        None => (
+            config.device.clone(),
+            config.get_filters(),
+            config.equalizer.clone(),
+            config.replay_gain,
+            false,
+            (0, config.preserve_pitch),
+            (1., 1.),
+        ),
+    };
+    let (repeat, shuffle) = app_player
+        .as_ref()
+        .map(|previous| (previous.repeat(), previous.shuffle()))
+        .unwrap_or_default();
+    let mut audio = AudioSource::from(track.clone());
+    // The device of the previous player is reopened by its exact name, a similar name could be another device
+    let device = device.as_deref().map(|device| match app_player {
+        Some(_) => DeviceSelector::Name(device),
+        None => DeviceSelector::Query(device),
+    });
+    let mut player = audio.play(
+        device,
+        filters.low_pass.cutoff(),
+        filters.high_pass.cutoff(),
+        debug,
+    )?;
+    debug.then(|| println!("[+] AudioSource created"));
+    debug.then(|| println!("[?] Queued {} tracks", tracks.len()));
+    player.set_queue(tracks, start)?;
+    player
+        .set_replay_gain(replay_gain)
+        .set_auto_limiter(config.auto_limiter)
+        .set_crossfade(config.get_crossfade())
+        .set_filters(filters)
+        .set_equalizer(equalizer)
+        .set_semitones(semitones)
+        .set_preserve_pitch(preserve_pitch)
+        .set_volume(volume)
+        .set_speed(speed)
+        .set_repeat(repeat)
+        .set_shuffle(shuffle);
+    player.set_limiter(limiting);
+    *audio_source = Some(audio);
+    *app_player = Some(player);
+    app_state_play.set_track(&track.path).set_debug(debug);
+    Ok(())
+}
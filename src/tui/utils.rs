@@ -1,68 +1,156 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use lofty::{
     config::WriteOptions,
     file::{AudioFile, TaggedFile, TaggedFileExt},
     probe::Probe,
-    tag::{Accessor, Tag, TagExt},
+    tag::{Accessor, ItemKey, Tag, TagExt},
 };
+use rayon::prelude::*;
 
-use crate::cli::Cli;
+use crate::{
+    audio::{
+        Measurement, ReplayGain, Track, expand_cue_sheets, is_cue_sheet, measure_album_loudness,
+        measure_loudness, verify_audio_file,
+    },
+    cli::Cli,
+    error::{Error, Result},
+    playlist::{Entry, Playlist},
+};
 
-const VALID_EXTENSIONS: &[&str; 5] = &["mp3", "wav", "m4a", "ogg", "flac"];
+#[doc = "List the audio files of a directory sorted by name, sub directories are walked in order when `recursive` is set"]
+pub fn list_audio_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut entries: Vec<std::fs::DirEntry> = std::fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).collect())
+        .unwrap_or_default();
+    entries.sort_by_key(|entry| entry.file_name());
+    entries
+        .into_iter()
+        .flat_map(|entry| {
+            let path = entry.path();
+            // Symbolic links to directories are not followed, a loop would never end
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() && recursive => list_audio_files(&path, true),
+                Ok(file_type) if file_type.is_dir() || path.is_dir() => Vec::new(),
+                Ok(_) => verify_audio_file(&path).into_iter().collect(),
+                Err(_) => Vec::new(),
+            }
+        })
+        .collect()
+}
 
-pub fn verify_path_extension(path: &PathBuf) -> Option<PathBuf> {
-    let ext_str = path.extension()?.to_str()?;
-    VALID_EXTENSIONS
+#[doc = "Parse `ss`, `mm:ss` or `hh:mm:ss` into a duration, the fields after the first one must be below 60"]
+pub fn parse_timestamp(input: &str) -> Option<Duration> {
+    let parts = input
+        .trim()
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if parts.is_empty() || parts.len() > 3 || parts[1..].iter().any(|part| *part >= 60) {
+        return None;
+    }
+    parts
         .iter()
-        .find(|val_ext_str| val_ext_str.to_lowercase() == ext_str)
-        .is_some()
-        .then(|| return Some(path.clone()))?
+        .try_fold(0u64, |total, part| {
+            total.checked_mul(60)?.checked_add(*part)
+        })
+        .map(Duration::from_secs)
 }
 
-pub fn get_total_duration(path: &PathBuf) -> Duration {
-    let tagged_file = get_tagged_file(path);
-    tagged_file.properties().duration()
+#[doc = "Format a duration as `mm:ss`"]
+pub fn format_timestamp(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
 }
 
-pub fn get_sample_rate(path: &PathBuf) -> String {
-    let tagged_file = get_tagged_file(path);
-    format!(
-        "{}{}",
-        tagged_file.properties().sample_rate().unwrap_or_default(),
-        tagged_file
-            .properties()
-            .sample_rate()
-            .is_some()
-            .then(|| "k")
-            .unwrap()
-    )
+pub fn get_total_duration(path: &PathBuf) -> Result<Duration> {
+    match get_tagged_file(path) {
+        Ok(tagged_file) => Ok(tagged_file.properties().duration()),
+        Err(e) => crate::audio::decoded_duration(path).ok_or(e),
+    }
 }
 
-pub fn get_tags(path: &PathBuf) -> Tag {
-    let mut tagged_file = get_tagged_file(path);
-    let tag = match tagged_file.primary_tag() {
-        Some(t) => t,
-        None => {
-            if let Some(tag) = tagged_file.first_tag() {
-                tag
-            } else {
-                let tag_type = tagged_file.primary_tag_type();
-                tagged_file.insert_tag(Tag::new(tag_type));
-                tagged_file
-                    .primary_tag_mut()
-                    .expect("[x] Lofty: Error while applying new tag on media")
+#[doc = "Sample rate formatted in kHz, empty when the file does not report it"]
+pub fn get_sample_rate(path: &PathBuf) -> Result<String> {
+    let tagged_file = get_tagged_file(path)?;
+    Ok(tagged_file
+        .properties()
+        .sample_rate()
+        .map(|rate| format!("{}k", rate as f32 / 1000.))
+        .unwrap_or_default())
+}
+
+pub fn get_tags(path: &PathBuf) -> Result<Tag> {
+    let tagged_file = get_tagged_file(path)?;
+    Ok(tagged_file
+        .primary_tag()
+        .or(tagged_file.first_tag())
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type())))
+}
+
+#[doc = "Playable tracks of a playlist, files described by a CUE sheet expanded to its tracks, and the entries that are missing or not audio files"]
+pub fn read_playlist(path: &Path) -> Result<(Vec<Track>, Vec<String>)> {
+    let Playlist {
+        tracks,
+        mut missing,
+    } = Playlist::load(path)?;
+    let (tracks, unsupported): (Vec<PathBuf>, Vec<PathBuf>) = tracks
+        .into_iter()
+        .partition(|track| verify_audio_file(track).is_some() || is_cue_sheet(track));
+    missing.extend(
+        unsupported
+            .iter()
+            .map(|track| track.to_string_lossy().to_string()),
+    );
+    let tracks = expand_cue_sheets(tracks);
+    if tracks.is_empty() {
+        return Err(Error::EmptyPlaylist(path.to_path_buf()));
+    }
+    Ok((tracks, missing))
+}
+
+#[doc = "Read a playlist given on the command line, the entries that cannot be played are reported on stderr"]
+pub fn load_playlist(path: &Path) -> Result<Vec<Track>> {
+    let (tracks, missing) = read_playlist(path)?;
+    missing
+        .iter()
+        .for_each(|entry| eprintln!("[!] Playlist: Skipping missing entry {entry}"));
+    Ok(tracks)
+}
+
+#[doc = "Save `tracks` as a playlist, with their durations and the titles and artists of their tags, the tracks of a CUE sheet are written once as their file"]
+pub fn save_playlist(path: &Path, tracks: &[Track]) -> Result<()> {
+    let mut files: Vec<&PathBuf> = tracks.iter().map(|track| &track.path).collect();
+    files.dedup();
+    let entries: Vec<Entry> = files
+        .into_iter()
+        .map(|track| {
+            let tag = get_tags(track).ok();
+            Entry {
+                path: track.clone(),
+                title: tag
+                    .as_ref()
+                    .and_then(|tag| tag.title().map(|title| title.to_string())),
+                artist: tag
+                    .as_ref()
+                    .and_then(|tag| tag.artist().map(|artist| artist.to_string())),
+                duration: get_total_duration(track).ok(),
             }
-        }
-    };
-    tag.clone()
+        })
+        .collect();
+    crate::playlist::save(path, &entries)
 }
 
-pub fn get_tagged_file(path: &PathBuf) -> TaggedFile {
+pub fn get_tagged_file(path: &PathBuf) -> Result<TaggedFile> {
     Probe::open(path)
-        .expect("[x] Lofty: Could not open path")
+        .map_err(|e| Error::TagRead(path.clone(), e))?
         .read()
-        .expect("[x] Lofty: Could not read path")
+        .map_err(|e| Error::TagRead(path.clone(), e))
 }
 
 pub fn tag_writter(
@@ -72,39 +160,31 @@ pub fn tag_writter(
     album: Option<String>,
     genre: Option<String>,
     path: PathBuf,
-) {
-    let audio_p = verify_path_extension(&path).expect("[x] Invalid file or extension");
-    let mut tagged_file = get_tagged_file(&audio_p);
-    let tag = match tagged_file.primary_tag_mut() {
-        Some(t) => t,
+) -> Result<()> {
+    let audio_p = verify_audio_file(&path).ok_or(Error::UnsupportedFormat(path.clone()))?;
+    let tagged_file = get_tagged_file(&audio_p)?;
+    let mut tag = match tagged_file.primary_tag().or(tagged_file.first_tag()) {
+        Some(t) => t.clone(),
         None => {
-            if let Some(t) = tagged_file.first_tag_mut() {
-                t
-            } else {
-                let tag_type = tagged_file.primary_tag_type();
-                cli.get_debug().then(|| {
-                    eprintln!("[!] Lofty: No tags found, creating a new tag of type `{tag_type:?}`")
-                });
-                tagged_file.insert_tag(Tag::new(tag_type));
-                tagged_file.primary_tag_mut().unwrap()
-            }
+            let tag_type = tagged_file.primary_tag_type();
+            cli.get_debug().then(|| {
+                eprintln!("[!] Lofty: No tags found, creating a new tag of type `{tag_type:?}`")
+            });
+            Tag::new(tag_type)
         }
     };
     if title.is_none() && artist.is_none() && album.is_none() && genre.is_none() {
-        let title =
-            inquire::prompt_text("Track Title: ").expect("[x] Inquire: Failed to retrieve title");
+        let title = inquire::prompt_text("Track Title: ")?;
         (!title.is_empty()).then(|| tag.set_title(title));
-        let artist = inquire::prompt_text("Artist Name: ")
-            .expect("[x] Inquire: Failed to retrieve Artist Name");
+        let artist = inquire::prompt_text("Artist Name: ")?;
         (!artist.is_empty()).then(|| tag.set_artist(artist));
-        let album = inquire::prompt_text("Album Name: ")
-            .expect("[x] Inquire: Failed to retrieve Album name");
+        let album = inquire::prompt_text("Album Name: ")?;
         (!album.is_empty()).then(|| tag.set_album(album));
-        let genre = inquire::prompt_text("Genre: ").expect("[x] Inquire: Failed to retrieve Genre");
-        (!genre.is_empty()).then(|| tag.set_album(genre));
-        tag.save_to_path(path, WriteOptions::default())
-            .expect("[x] Lofty: Failed to write the tag");
-        std::process::exit(0);
+        let genre = inquire::prompt_text("Genre: ")?;
+        (!genre.is_empty()).then(|| tag.set_genre(genre));
+        return tag
+            .save_to_path(&path, WriteOptions::default())
+            .map_err(|e| Error::TagWrite(path.clone(), e));
     }
     if let Some(title) = title {
         tag.set_title(title.clone());
@@ -126,7 +206,138 @@ pub fn tag_writter(
         cli.get_debug()
             .then(|| println!("[!] Genre tag set to {}", genre));
     }
+    tag.save_to_path(&path, WriteOptions::default())
+        .map_err(|e| Error::TagWrite(path.clone(), e))
+}
+
+#[doc = "Write the ReplayGain 2.0 tags of `gain`, the ones it does not have are left untouched"]
+pub fn write_replay_gain(path: &PathBuf, gain: &ReplayGain) -> Result<()> {
+    let mut tag = get_tags(path)?;
+    [
+        (
+            ItemKey::ReplayGainTrackGain,
+            gain.track_gain.map(|gain| format!("{gain:.2} dB")),
+        ),
+        (
+            ItemKey::ReplayGainTrackPeak,
+            gain.track_peak.map(|peak| format!("{peak:.6}")),
+        ),
+        (
+            ItemKey::ReplayGainAlbumGain,
+            gain.album_gain.map(|gain| format!("{gain:.2} dB")),
+        ),
+        (
+            ItemKey::ReplayGainAlbumPeak,
+            gain.album_peak.map(|peak| format!("{peak:.6}")),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?)))
+    .for_each(|(key, value)| {
+        tag.insert_text(key, value);
+    });
     tag.save_to_path(path, WriteOptions::default())
-        .expect("[x] Lofty: Failed to write the tag");
-    std::process::exit(0);
+        .map_err(|e| Error::TagWrite(path.clone(), e))
+}
+
+fn print_loudness(name: &str, integrated: Option<f64>, range: Option<f64>, true_peak: f64) {
+    let Some(integrated) = integrated else {
+        println!("{name}: silent");
+        return;
+    };
+    println!(
+        "{name}: {integrated:.1} LUFS |{} True Peak {:+.1} dBTP | Gain {:+.2} dB",
+        range.map_or(String::new(), |range| format!(" LRA {range:.1} LU |")),
+        20. * true_peak.log10(),
+        crate::audio::REPLAY_GAIN_REFERENCE - integrated,
+    );
+}
+
+#[doc = "Measure the loudness of `path`, every audio file under it when it is a directory, and optionally tag them"]
+pub fn scan_loudness(cli: &Cli, path: PathBuf, album: bool, write: bool) -> Result<()> {
+    let files = if path.is_dir() {
+        list_audio_files(&path, true)
+    } else {
+        vec![verify_audio_file(&path).ok_or(Error::UnsupportedFormat(path.clone()))?]
+    };
+    cli.get_debug()
+        .then(|| println!("[?] Measuring the loudness of {} files", files.len()));
+    let measurements: Vec<Measurement> = files
+        .par_iter()
+        .map(measure_loudness)
+        .collect::<Vec<Result<Measurement>>>()
+        .into_iter()
+        .filter_map(|measurement| {
+            measurement
+                .inspect_err(|e| eprintln!("[x] Loudness: {e}"))
+                .ok()
+        })
+        .collect();
+    let mut gains: Vec<ReplayGain> = measurements
+        .iter()
+        .map(|measurement| {
+            let name = measurement
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            print_loudness(
+                &name,
+                measurement.integrated,
+                Some(measurement.range),
+                measurement.true_peak,
+            );
+            ReplayGain {
+                track_gain: measurement.gain().map(|gain| gain as f32),
+                track_peak: Some(measurement.true_peak as f32),
+                ..ReplayGain::default()
+            }
+        })
+        .collect();
+    if album {
+        let mut albums: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (index, measurement) in measurements.iter().enumerate() {
+            if let Some(name) = get_tags(&measurement.path)
+                .ok()
+                .and_then(|tag| tag.album().map(|album| album.to_string()))
+                .filter(|album| !album.is_empty())
+            {
+                albums.entry(name).or_default().push(index);
+            }
+        }
+        for (name, tracks) in albums {
+            let (integrated, true_peak) = measure_album_loudness(
+                &tracks
+                    .iter()
+                    .map(|index| &measurements[*index])
+                    .collect::<Vec<&Measurement>>(),
+            );
+            print_loudness(&format!("Album {name}"), integrated, None, true_peak);
+            for index in tracks {
+                gains[index].album_gain = integrated
+                    .map(|integrated| (crate::audio::REPLAY_GAIN_REFERENCE - integrated) as f32);
+                gains[index].album_peak = Some(true_peak as f32);
+            }
+        }
+    }
+    if !write {
+        return Ok(());
+    }
+    for (measurement, gain) in measurements.iter().zip(&gains) {
+        if gain.track_gain.is_none() {
+            continue;
+        }
+        match write_replay_gain(&measurement.path, gain) {
+            Ok(()) => {
+                cli.get_debug().then(|| {
+                    println!(
+                        "[!] ReplayGain tags written to {}",
+                        measurement.path.to_string_lossy()
+                    )
+                });
+            }
+            Err(e) => eprintln!("[x] {e}"),
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use ratatui::prelude::*;
+
+use crate::audio::Waveform;
+
+#[doc = "Bits of the dots of the left and right columns of a braille cell, from the top row down"]
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+#[doc = "Min/max overview of the whole track in braille, two columns and four rows of dots per cell"]
+pub struct WaveformView {
+    pub waveform: Arc<Waveform>,
+    #[doc = "Played fraction of the track, from 0 to 1"]
+    pub position: f64,
+    #[doc = "Fractions of the file the track spans, the whole file unless it is a CUE track"]
+    pub range: (f64, f64),
+}
+
+impl Widget for WaveformView {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let all = &self.waveform.buckets;
+        let (from, to) = self.range;
+        let from = ((from.clamp(0., 1.) * all.len() as f64) as usize).min(all.len());
+        let to = ((to.clamp(0., 1.) * all.len() as f64).ceil() as usize).clamp(from, all.len());
+        let buckets = &all[from..to];
+        if area.width == 0 || area.height == 0 || buckets.is_empty() {
+            return;
+        }
+        let columns = area.width as usize * 2;
+        let rows = area.height as usize * 4;
+        let scale = self.waveform.peak().max(f32::EPSILON);
+        // Dot row of an amplitude, 0 at the top of the area
+        let row = |amplitude: f32| {
+            let ratio = (1. - amplitude / scale) / 2.;
+            ((ratio * rows as f32) as usize).min(rows - 1)
+        };
+        let playhead =
+            ((self.position.clamp(0., 1.) * area.width as f64) as u16).min(area.width - 1);
+        for x in 0..area.width {
+            let mut cells = vec![0u32; area.height as usize];
+            for (side, dots) in BRAILLE_DOTS.iter().enumerate() {
+                let column = x as usize * 2 + side;
+                let start = column * buckets.len() / columns;
+                let end = ((column + 1) * buckets.len() / columns).max(start + 1);
+                let (min, max) = buckets[start..end.min(buckets.len())]
+                    .iter()
+                    .fold((0f32, 0f32), |(min, max), bucket| {
+                        (min.min(bucket.0), max.max(bucket.1))
+                    });
+                for dot in row(max)..=row(min) {
+                    cells[dot / 4] |= dots[dot % 4];
+                }
+            }
+            let color = match x.cmp(&playhead) {
+                std::cmp::Ordering::Less => Color::Yellow,
+                std::cmp::Ordering::Equal => Color::Red,
+                std::cmp::Ordering::Greater => Color::DarkGray,
+            };
+            for (y, bits) in cells.into_iter().enumerate() {
+                let symbol = char::from_u32(0x2800 + bits).unwrap_or(' ');
+                let cell = &mut buf[(area.x + x, area.y + y as u16)];
+                cell.set_char(symbol).set_fg(color);
+                (x == playhead).then(|| cell.set_bg(Color::DarkGray));
+            }
+        }
+    }
+}